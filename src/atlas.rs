@@ -0,0 +1,1165 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{anyhow, bail, Context};
+use image::{GenericImage, RgbaImage};
+
+use crate::{
+    config::{
+        AlternativeConfig, AnimationConfig, CollisionConfig, Config, NavigationArea,
+        NavigationConfig, OcclusionLayerConfig, Packing, RenderingConfig, TerrainMode,
+        TileSetConfig,
+    },
+    godot::{
+        resource::{
+            custom_data_values, rgba, AlternativeTile, NavigationPolygon, OccluderPolygon,
+            PeeringBit, PhysicsPolygon, Tile, TileAnimation, TileRendering, TileSetResource,
+        },
+        Vector2i,
+    },
+    polygon::{cell_polygon, make_polygons, polygon_area, trace_polygons, transparent_mesh},
+    report::AtlasUsage,
+    terrain::{load_terrain_tiles, transform_peering_bit},
+    tile::load_tiles,
+    Error, Project,
+};
+
+/// Builds the atlas images and tile layouts for a project.
+pub struct AtlasBuilder<'a> {
+    project: &'a Project,
+    previous_layouts: Vec<&'a [Tile]>,
+}
+
+impl<'a> AtlasBuilder<'a> {
+    pub fn new(project: &'a Project) -> Self {
+        AtlasBuilder {
+            project,
+            previous_layouts: Vec::new(),
+        }
+    }
+
+    /// The layouts of the atlas sources from the previous export. Terrain
+    /// tiles keep their atlas sources and cells from them, when possible.
+    pub fn previous_layouts(mut self, previous_layouts: Vec<&'a [Tile]>) -> Self {
+        self.previous_layouts = previous_layouts;
+        self
+    }
+
+    pub fn build(self) -> Result<Atlas, Error> {
+        let config = &self.project.config;
+        let tiles = load_tiles(&self.project.directory, config).map_err(Error::Tiles)?;
+        let terrain_tiles =
+            load_terrain_tiles(&self.project.directory, config).map_err(Error::Terrains)?;
+
+        // Explicitly positioned tiles are in the first atlas source.
+        let mut occupied = vec![HashSet::new()];
+
+        let grid = Grid {
+            tile_size: config.tile_set.tile_size,
+            margins: config.tile_set.margins,
+            separation: config.tile_set.separation,
+        };
+        let max_grid_size = max_grid_size(&config.tile_set, &grid);
+        if max_grid_size.is_some_and(|size| size.contains(&0)) {
+            return Err(Error::Config(anyhow!(
+                "expected 'max_texture_size' to fit at least one tile"
+            )));
+        }
+
+        let column_limit = column_limit(&config.tile_set, &grid);
+        let row_limit = max_grid_size.map(|[_, rows]| rows);
+
+        let tile_animations = tiles
+            .iter()
+            .map(|tile| tile_animation(tile.config.animation.as_ref()))
+            .collect::<Vec<_>>();
+        let set_animations = config
+            .terrain_sets
+            .iter()
+            .map(|set| tile_animation(set.animation.as_ref()))
+            .collect::<Vec<_>>();
+
+        for (tile, animation) in tiles.iter().zip(&tile_animations) {
+            let cells = covered_cells(tile.config.position, tile.config.size, animation.as_ref());
+
+            for [x, y] in cells {
+                if let Some(columns) = column_limit.filter(|&columns| x >= columns) {
+                    return Err(Error::Config(anyhow!(
+                        "tile {:?} is outside the atlas, which has {columns} columns",
+                        tile.config.name
+                    )));
+                }
+
+                if let Some(rows) = row_limit.filter(|&rows| y >= rows) {
+                    return Err(Error::Config(anyhow!(
+                        "tile {:?} is outside the atlas, which has {rows} rows",
+                        tile.config.name
+                    )));
+                }
+
+                if !occupied[0].insert([x, y]) {
+                    return Err(Error::Config(anyhow!(
+                        "tile {:?} overlaps another tile at ({x}, {y})",
+                        tile.config.name
+                    )));
+                }
+            }
+        }
+
+        // Terrain tiles keep the cells they had in the previous export, as long
+        // as those cells haven't been claimed by explicitly positioned tiles.
+        // Variants have the same terrains, so they take the cells in order.
+        // Additional atlas sources are only kept while there's a size limit.
+        let page_limit = if max_grid_size.is_some() {
+            usize::MAX
+        } else {
+            1
+        };
+        let mut previous_positions = HashMap::<_, VecDeque<_>>::new();
+        for (page, previous_layout) in self.previous_layouts.iter().enumerate().take(page_limit) {
+            for tile in previous_layout.iter() {
+                let (Ok(x), Ok(y)) = (
+                    u32::try_from(tile.position.x),
+                    u32::try_from(tile.position.y),
+                ) else {
+                    continue;
+                };
+
+                if tile.terrain_set.is_some() {
+                    previous_positions
+                        .entry((
+                            tile.terrain_set,
+                            tile.terrain,
+                            tile.terrains_peering_bit.clone(),
+                        ))
+                        .or_default()
+                        .push_back((page, [x, y]));
+                }
+            }
+        }
+
+        let mut placed_terrain_tiles = Vec::new();
+        let mut unplaced_terrain_tiles = Vec::new();
+
+        for tile in terrain_tiles {
+            let previous_position = previous_positions
+                .get_mut(&(
+                    Some(tile.terrain.terrain_set as u32),
+                    Some(tile.terrain.terrain as u32),
+                    tile.terrains_peering_bit.clone(),
+                ))
+                .and_then(VecDeque::pop_front);
+            let animation = set_animations[tile.terrain.terrain_set].as_ref();
+
+            match previous_position {
+                Some((page, position))
+                    if frame_cells(position, [1, 1], animation)
+                        .iter()
+                        .all(|&[x, y]| {
+                            !occupied
+                                .get(page)
+                                .is_some_and(|cells| cells.contains(&[x, y]))
+                                && column_limit.is_none_or(|columns| x < columns)
+                                && row_limit.is_none_or(|rows| y < rows)
+                        }) =>
+                {
+                    if occupied.len() <= page {
+                        occupied.resize_with(page + 1, HashSet::new);
+                    }
+
+                    occupied[page].extend(frame_cells(position, [1, 1], animation));
+                    placed_terrain_tiles.push((page, position, tile))
+                }
+                _ => unplaced_terrain_tiles.push(tile),
+            }
+        }
+
+        let unplaced_animations = unplaced_terrain_tiles
+            .iter()
+            .map(|tile| set_animations[tile.terrain.terrain_set].as_ref())
+            .collect::<Vec<_>>();
+
+        // The unplaced tiles fill up the atlas sources in order, and the ones
+        // that don't fit go into new atlas sources.
+        let mut image_sizes = Vec::new();
+        let mut unplaced_positions = Vec::new();
+        let mut remaining_animations = &*unplaced_animations;
+
+        for page in 0.. {
+            if page >= occupied.len() {
+                if remaining_animations.is_empty() {
+                    break;
+                }
+
+                occupied.push(HashSet::new());
+            }
+
+            let frame_count = |animation: &Option<&TileAnimation>| {
+                animation.map_or(1, |animation| animation.frame_durations.len())
+            };
+            let used_cells =
+                occupied[page].len() + remaining_animations.iter().map(frame_count).sum::<usize>();
+
+            let (image_size, positions) = pack_page(
+                &config.tile_set,
+                &grid,
+                &occupied[page],
+                remaining_animations,
+                used_cells,
+            )
+            .map_err(Error::Config)?;
+
+            if positions.is_empty() && occupied[page].is_empty() && !remaining_animations.is_empty()
+            {
+                return Err(Error::Config(anyhow!(
+                    "a terrain animation doesn't fit in 'max_texture_size'"
+                )));
+            }
+
+            for (&position, &animation) in positions.iter().zip(remaining_animations) {
+                occupied[page].extend(frame_cells(position, [1, 1], animation));
+                unplaced_positions.push((page, position));
+            }
+
+            remaining_animations = &remaining_animations[positions.len()..];
+            image_sizes.push(image_size);
+        }
+
+        let mut images = image_sizes
+            .iter()
+            .map(|&[width, height]| RgbaImage::new(width, height))
+            .collect::<Vec<_>>();
+        let mut layouts = vec![Vec::new(); images.len()];
+        let extrusion = config.tile_set.extrusion;
+
+        for (tile, animation) in tiles.iter().zip(tile_animations) {
+            let [x, y] = tile.config.position;
+
+            for (frame, [x, y]) in
+                tile.frames
+                    .iter()
+                    .zip(frame_cells([x, y], tile.config.size, animation.as_ref()))
+            {
+                copy_frame(&mut images[0], frame, grid.cell_position([x, y]), extrusion);
+            }
+
+            let custom_data =
+                custom_data_values(&tile.config.custom_data, &config.custom_data_layers)
+                    .with_context(|| format!("invalid custom data for tile {:?}", tile.config.name))
+                    .map_err(Error::Config)?;
+
+            let first_frame = &tile.frames[0];
+            layouts[0].push(Tile {
+                position: Vector2i::from([x, y]),
+                size_in_atlas: Vector2i::from(tile.config.size),
+                terrain_set: None,
+                terrain: None,
+                terrains_peering_bit: Default::default(),
+                probability: None,
+                occluder_polygons: occluder_polygons(&config.occlusion_layers, first_frame),
+                physics_polygons: physics_polygons(&tile.config.collision, first_frame),
+                navigation_polygons: navigation_polygons(
+                    &tile.config.navigation,
+                    &config.tile_set,
+                    first_frame,
+                ),
+                custom_data,
+                animation,
+                alternatives: alternative_tiles(&tile.config.alternatives, config, None)
+                    .with_context(|| {
+                        format!("invalid alternatives for tile {:?}", tile.config.name)
+                    })
+                    .map_err(Error::Config)?,
+                rendering: tile_rendering(&tile.config.rendering),
+                properties: Vec::new(),
+            })
+        }
+
+        let terrain_tiles = placed_terrain_tiles.into_iter().chain(
+            unplaced_positions
+                .into_iter()
+                .zip(unplaced_terrain_tiles)
+                .map(|((page, position), tile)| (page, position, tile)),
+        );
+
+        for (page, [x, y], tile) in terrain_tiles {
+            let terrain_config =
+                &config.terrain_sets[tile.terrain.terrain_set].terrains[tile.terrain.terrain];
+            let animation = set_animations[tile.terrain.terrain_set].clone();
+
+            for (frame, [x, y]) in
+                tile.frames
+                    .iter()
+                    .zip(frame_cells([x, y], [1, 1], animation.as_ref()))
+            {
+                copy_frame(
+                    &mut images[page],
+                    frame,
+                    grid.cell_position([x, y]),
+                    extrusion,
+                );
+            }
+
+            let custom_data =
+                custom_data_values(&terrain_config.custom_data, &config.custom_data_layers)
+                    .with_context(|| {
+                        format!("invalid custom data for terrain {:?}", terrain_config.name)
+                    })
+                    .map_err(Error::Config)?;
+
+            let alternatives = alternative_tiles(
+                &terrain_config.alternatives,
+                config,
+                Some((
+                    config.terrain_sets[tile.terrain.terrain_set].mode,
+                    &tile.terrains_peering_bit,
+                )),
+            )
+            .with_context(|| format!("invalid alternatives for terrain {:?}", terrain_config.name))
+            .map_err(Error::Config)?;
+
+            let first_frame = &tile.frames[0];
+            layouts[page].push(Tile {
+                position: Vector2i::from([x, y]),
+                size_in_atlas: Vector2i { x: 1, y: 1 },
+                terrain_set: Some(tile.terrain.terrain_set as u32),
+                terrain: Some(tile.terrain.terrain as u32),
+                terrains_peering_bit: tile.terrains_peering_bit,
+                probability: Some(tile.probability),
+                occluder_polygons: occluder_polygons(&config.occlusion_layers, first_frame),
+                physics_polygons: physics_polygons(&terrain_config.collision, first_frame),
+                navigation_polygons: navigation_polygons(
+                    &terrain_config.navigation,
+                    &config.tile_set,
+                    first_frame,
+                ),
+                custom_data,
+                animation,
+                alternatives,
+                rendering: tile_rendering(&terrain_config.rendering),
+                properties: Vec::new(),
+            })
+        }
+
+        let usage = image_sizes
+            .iter()
+            .zip(&occupied)
+            .map(|(&image_size, occupied)| AtlasUsage {
+                image_size,
+                grid_size: [
+                    grid.cell_count(0, image_size[0]),
+                    grid.cell_count(1, image_size[1]),
+                ],
+                used_cells: occupied.len() as u32,
+            })
+            .collect();
+
+        Ok(Atlas {
+            images,
+            layouts,
+            usage,
+            tile_size: config.tile_set.tile_size,
+            margins: config.tile_set.margins,
+            separation: config.tile_set.separation,
+        })
+    }
+}
+
+/// The placement of the atlas cells in the image.
+struct Grid {
+    tile_size: [u32; 2],
+    margins: [u32; 2],
+    separation: [u32; 2],
+}
+
+impl Grid {
+    /// The pixel position of a cell's top left corner.
+    fn cell_position(&self, cell: [u32; 2]) -> [u32; 2] {
+        [0, 1].map(|axis| {
+            self.margins[axis] + cell[axis] * (self.tile_size[axis] + self.separation[axis])
+        })
+    }
+
+    /// The image width or height that fits a number of cells, including the
+    /// margins on both sides.
+    fn image_length(&self, axis: usize, cells: u32) -> u32 {
+        2 * self.margins[axis] + cells * (self.tile_size[axis] + self.separation[axis])
+            - self.separation[axis]
+    }
+
+    /// The number of cells that fit in an image width or height.
+    fn cell_count(&self, axis: usize, image_length: u32) -> u32 {
+        (image_length.saturating_sub(2 * self.margins[axis]) + self.separation[axis])
+            / (self.tile_size[axis] + self.separation[axis])
+    }
+}
+
+/// Copies a frame into the atlas image, and repeats its edges outwards by
+/// `extrusion` pixels.
+fn copy_frame(image: &mut RgbaImage, frame: &RgbaImage, [left, top]: [u32; 2], extrusion: u32) {
+    image
+        .copy_from(frame, left, top)
+        .expect("there should be enough room in the image for the tiles");
+
+    let right = left + frame.width() - 1;
+    let bottom = top + frame.height() - 1;
+
+    for y in top..=bottom {
+        for offset in 1..=extrusion {
+            image.put_pixel(left - offset, y, *image.get_pixel(left, y));
+            image.put_pixel(right + offset, y, *image.get_pixel(right, y));
+        }
+    }
+
+    // The extruded columns are included, to fill the corners.
+    for x in left - extrusion..=right + extrusion {
+        for offset in 1..=extrusion {
+            image.put_pixel(x, top - offset, *image.get_pixel(x, top));
+            image.put_pixel(x, bottom + offset, *image.get_pixel(x, bottom));
+        }
+    }
+}
+
+fn tile_animation(config: Option<&AnimationConfig>) -> Option<TileAnimation> {
+    config.map(|config| TileAnimation {
+        columns: config.columns,
+        separation: Vector2i::from(config.separation),
+        speed: config.speed,
+        frame_durations: config.frames.clone(),
+    })
+}
+
+/// The first atlas cells of a tile's animation frames, or only the tile's
+/// first cell if it's not animated.
+fn frame_cells(
+    position: [u32; 2],
+    size: [u32; 2],
+    animation: Option<&TileAnimation>,
+) -> Vec<[u32; 2]> {
+    let Some(animation) = animation else {
+        return vec![position];
+    };
+
+    let [x, y] = position;
+    (0..animation.frame_durations.len())
+        .map(|frame| {
+            let offset = animation.frame_offset(frame, Vector2i::from(size));
+            [x + offset.x as u32, y + offset.y as u32]
+        })
+        .collect()
+}
+
+/// All atlas cells that a tile's animation frames cover.
+fn covered_cells(
+    position: [u32; 2],
+    [width, height]: [u32; 2],
+    animation: Option<&TileAnimation>,
+) -> Vec<[u32; 2]> {
+    frame_cells(position, [width, height], animation)
+        .into_iter()
+        .flat_map(|[x, y]| (y..y + height).flat_map(move |y| (x..x + width).map(move |x| [x, y])))
+        .collect()
+}
+
+/// Packs the unplaced terrain tiles into an atlas image, and rounds its size
+/// up to powers of two if that's configured. Returns the size of the image,
+/// and the positions of the first tiles that fit in it, which are all of them
+/// unless there's a maximum texture size.
+fn pack_page(
+    tile_set: &TileSetConfig,
+    grid: &Grid,
+    occupied: &HashSet<[u32; 2]>,
+    animations: &[Option<&TileAnimation>],
+    used_cells: usize,
+) -> anyhow::Result<([u32; 2], Vec<[u32; 2]>)> {
+    let round_size = |image_size: [u32; 2]| {
+        if tile_set.power_of_two {
+            image_size.map(u32::next_power_of_two)
+        } else {
+            image_size
+        }
+    };
+
+    let (image_size, positions) = pack(tile_set, grid, occupied, animations, used_cells)?;
+    let image_size = round_size(image_size);
+
+    let (Some(max_texture_size), Some([_, rows])) =
+        (tile_set.max_texture_size, max_grid_size(tile_set, grid))
+    else {
+        return Ok((image_size, positions));
+    };
+
+    if image_size.iter().all(|&length| length <= max_texture_size) {
+        return Ok((image_size, positions));
+    }
+
+    // The image is filled up to the maximum size, and cropped to the used
+    // cells.
+    let columns =
+        column_limit(tile_set, grid).expect("there should be a column limit with a maximum size");
+    let positions = find_free_positions(animations, occupied, [columns, rows]);
+    let [used_columns, used_rows] = used_grid_size(occupied, &positions, animations);
+
+    let columns = match tile_set.packing {
+        Packing::Columns(_) => columns,
+        _ => used_columns,
+    };
+
+    Ok((
+        round_size([
+            grid.image_length(0, columns),
+            grid.image_length(1, used_rows),
+        ]),
+        positions,
+    ))
+}
+
+/// Finds positions for the unplaced terrain tiles, and the size of an image
+/// that fits them and the already placed tiles.
+fn pack(
+    tile_set: &TileSetConfig,
+    grid: &Grid,
+    occupied: &HashSet<[u32; 2]>,
+    animations: &[Option<&TileAnimation>],
+    used_cells: usize,
+) -> anyhow::Result<([u32; 2], Vec<[u32; 2]>)> {
+    // The number of columns that the frames of an animation span.
+    let animation_width = |animation| {
+        frame_cells([0, 0], [1, 1], animation)
+            .into_iter()
+            .map(|[x, _]| x + 1)
+            .max()
+            .unwrap_or(1)
+    };
+
+    let columns = match tile_set.packing {
+        Packing::Square => return Ok(pack_square(grid, occupied, animations, used_cells)),
+        Packing::Columns(_) | Packing::MaxWidth(_) => {
+            column_limit(tile_set, grid).expect("there should be a column limit with a fixed width")
+        }
+        Packing::Tight => {
+            let [pitch_x, pitch_y] =
+                [0, 1].map(|axis| f64::from(grid.tile_size[axis] + grid.separation[axis]));
+            let square_columns = (used_cells as f64 * pitch_y / pitch_x).sqrt().ceil() as u32;
+
+            let columns = occupied
+                .iter()
+                .map(|&[x, _]| x + 1)
+                .chain(
+                    animations
+                        .iter()
+                        .map(|&animation| animation_width(animation)),
+                )
+                .fold(square_columns.max(1), u32::max);
+
+            column_limit(tile_set, grid).map_or(columns, |limit| columns.min(limit))
+        }
+    };
+
+    if columns == 0 {
+        bail!("expected the atlas to have room for at least one column");
+    }
+
+    if animations
+        .iter()
+        .any(|&animation| animation_width(animation) > columns)
+    {
+        bail!("a terrain animation is wider than the atlas, which has {columns} columns");
+    }
+
+    let min_rows = occupied
+        .iter()
+        .map(|&[_, y]| y + 1)
+        .max()
+        .unwrap_or(0)
+        .max((used_cells as u32).div_ceil(columns));
+    let positions = (min_rows..)
+        .map(|rows| find_free_positions(animations, occupied, [columns, rows]))
+        .find(|positions| positions.len() == animations.len())
+        .expect("the tiles should fit when there are enough rows");
+    let [used_columns, used_rows] = used_grid_size(occupied, &positions, animations);
+
+    let columns = match tile_set.packing {
+        Packing::Columns(_) => columns,
+        _ => used_columns,
+    };
+
+    Ok((
+        [
+            grid.image_length(0, columns),
+            grid.image_length(1, used_rows),
+        ],
+        positions,
+    ))
+}
+
+/// The number of columns that packing strategies with a fixed width, or the
+/// maximum texture size, allow.
+fn column_limit(tile_set: &TileSetConfig, grid: &Grid) -> Option<u32> {
+    let packing_limit = match tile_set.packing {
+        Packing::Columns(columns) => Some(columns),
+        Packing::MaxWidth(max_width) => {
+            Some(grid.cell_count(0, length_limit(max_width, tile_set.power_of_two)))
+        }
+        Packing::Square | Packing::Tight => None,
+    };
+    let texture_limit = max_grid_size(tile_set, grid).map(|[columns, _]| columns);
+
+    packing_limit.into_iter().chain(texture_limit).min()
+}
+
+/// The number of columns and rows that fit in an image of the maximum texture
+/// size.
+fn max_grid_size(tile_set: &TileSetConfig, grid: &Grid) -> Option<[u32; 2]> {
+    tile_set.max_texture_size.map(|max_texture_size| {
+        let max_length = length_limit(max_texture_size, tile_set.power_of_two);
+        [0, 1].map(|axis| grid.cell_count(axis, max_length))
+    })
+}
+
+/// The largest image width or height within a limit. Rounding it up to a power
+/// of two must stay within the limit.
+fn length_limit(limit: u32, power_of_two: bool) -> u32 {
+    match limit {
+        0 => 0,
+        limit if power_of_two => 1 << limit.ilog2(),
+        limit => limit,
+    }
+}
+
+/// The number of columns and rows that the occupied cells and the frames of
+/// the placed tiles cover.
+fn used_grid_size(
+    occupied: &HashSet<[u32; 2]>,
+    positions: &[[u32; 2]],
+    animations: &[Option<&TileAnimation>],
+) -> [u32; 2] {
+    let placed_cells = positions
+        .iter()
+        .zip(animations)
+        .flat_map(|(&position, &animation)| frame_cells(position, [1, 1], animation));
+
+    occupied
+        .iter()
+        .copied()
+        .chain(placed_cells)
+        .fold([1, 1], |[columns, rows], [x, y]| {
+            [columns.max(x + 1), rows.max(y + 1)]
+        })
+}
+
+/// Packs tiles into a square image, that grows one tile at a time until they
+/// all fit.
+fn pack_square(
+    grid: &Grid,
+    occupied: &HashSet<[u32; 2]>,
+    animations: &[Option<&TileAnimation>],
+    used_cells: usize,
+) -> ([u32; 2], Vec<[u32; 2]>) {
+    let size_step =
+        (grid.tile_size[0] + grid.separation[0]).max(grid.tile_size[1] + grid.separation[1]);
+
+    // Make room for at least one tile, so an empty tile set is still a
+    // valid image.
+    let mut image_size = grid.image_length(0, 1).max(grid.image_length(1, 1));
+
+    for &[x, y] in occupied {
+        let req_width = grid.image_length(0, x + 1);
+        let req_height = grid.image_length(1, y + 1);
+        let req_size = req_width.max(req_height);
+
+        image_size = image_size.max(req_size);
+    }
+
+    while grid.cell_count(0, image_size) * grid.cell_count(1, image_size) < used_cells as u32 {
+        image_size += size_step;
+    }
+
+    // Animation frames may not fit in the remaining gaps, so the image
+    // grows until they do.
+    loop {
+        let grid_size = [
+            grid.cell_count(0, image_size),
+            grid.cell_count(1, image_size),
+        ];
+        let positions = find_free_positions(animations, occupied, grid_size);
+        if positions.len() == animations.len() {
+            return ([image_size, image_size], positions);
+        }
+
+        image_size += size_step;
+    }
+}
+
+/// Finds the first free positions, row by row, where each tile and its
+/// animation frames fit. Stops at the first tile that doesn't fit in the grid.
+fn find_free_positions(
+    animations: &[Option<&TileAnimation>],
+    occupied: &HashSet<[u32; 2]>,
+    [columns, rows]: [u32; 2],
+) -> Vec<[u32; 2]> {
+    let mut occupied = occupied.clone();
+    let cells = (0..rows).flat_map(|y| (0..columns).map(move |x| [x, y]));
+    let mut first_free = 0;
+    let mut positions = Vec::new();
+
+    for &animation in animations {
+        let is_free = |&[x, y]: &[u32; 2]| x < columns && y < rows && !occupied.contains(&[x, y]);
+
+        first_free += cells
+            .clone()
+            .skip(first_free)
+            .take_while(|cell| !is_free(cell))
+            .count();
+
+        let Some(position) = cells
+            .clone()
+            .skip(first_free)
+            .find(|&position| frame_cells(position, [1, 1], animation).iter().all(is_free))
+        else {
+            break;
+        };
+
+        occupied.extend(frame_cells(position, [1, 1], animation));
+        positions.push(position);
+    }
+
+    positions
+}
+
+fn tile_rendering(config: &RenderingConfig) -> TileRendering {
+    TileRendering {
+        texture_origin: config.texture_origin.map(|[x, y]| Vector2i {
+            x: x.into(),
+            y: y.into(),
+        }),
+        z_index: config.z_index.map(Into::into),
+        y_sort_origin: config.y_sort_origin.map(Into::into),
+        material: config.material.clone(),
+    }
+}
+
+/// Makes alternatives for a tile. Terrain tiles, with the mode of their set,
+/// get their peering bits mirrored.
+fn alternative_tiles(
+    alternatives: &[AlternativeConfig],
+    config: &Config,
+    terrain: Option<(TerrainMode, &PeeringBit)>,
+) -> anyhow::Result<Vec<AlternativeTile>> {
+    alternatives
+        .iter()
+        .enumerate()
+        .map(|(index, alternative)| {
+            let id = index + 1;
+
+            let modulate = match alternative.modulate.as_deref() {
+                Some(components) => Some(rgba(components).ok_or_else(|| {
+                    anyhow!("expected 'modulate' of alternative {id} to have three or four numbers")
+                })?),
+                None => None,
+            };
+
+            let terrains_peering_bit = match terrain {
+                Some((mode, peering_bit)) => {
+                    transform_peering_bit(config, mode, peering_bit, alternative).ok_or_else(
+                        || {
+                            anyhow!(
+                                "alternative {id} can't be transposed with {:?} tiles",
+                                config.tile_set.tile_shape
+                            )
+                        },
+                    )?
+                }
+                None => PeeringBit::default(),
+            };
+
+            Ok(AlternativeTile {
+                flip_h: alternative.flip_h,
+                flip_v: alternative.flip_v,
+                transpose: alternative.transpose,
+                modulate,
+                terrains_peering_bit,
+                probability: alternative.probability,
+            })
+        })
+        .collect()
+}
+
+/// Traces an occluder for each occlusion layer. A tile can only have one
+/// occluder per layer, so the largest outline is used.
+fn occluder_polygons(layers: &[OcclusionLayerConfig], image: &RgbaImage) -> Vec<OccluderPolygon> {
+    layers
+        .iter()
+        .enumerate()
+        .filter_map(|(layer, config)| {
+            trace_polygons(image, config.tolerance)
+                .into_iter()
+                .max_by(|a, b| polygon_area(a).total_cmp(&polygon_area(b)))
+                .map(|points| OccluderPolygon { layer, points })
+        })
+        .collect()
+}
+
+fn physics_polygons(collision: &[CollisionConfig], image: &RgbaImage) -> Vec<PhysicsPolygon> {
+    collision
+        .iter()
+        .flat_map(|collision| {
+            make_polygons(&collision.polygons, image)
+                .into_iter()
+                .map(|points| PhysicsPolygon {
+                    layer: collision.layer,
+                    points,
+                })
+        })
+        .collect()
+}
+
+fn navigation_polygons(
+    navigation: &[NavigationConfig],
+    tile_set: &TileSetConfig,
+    image: &RgbaImage,
+) -> Vec<NavigationPolygon> {
+    let cell = cell_polygon(tile_set);
+
+    navigation
+        .iter()
+        .filter_map(|navigation| {
+            let (vertices, polygons, outlines) = match navigation.area {
+                NavigationArea::Cell => (
+                    cell.clone(),
+                    vec![(0..cell.len()).collect()],
+                    vec![cell.clone()],
+                ),
+                NavigationArea::Transparent => {
+                    let (vertices, polygons) = transparent_mesh(image, &cell);
+                    if polygons.is_empty() {
+                        return None;
+                    }
+
+                    (vertices, polygons, Vec::new())
+                }
+                NavigationArea::None => return None,
+            };
+
+            Some(NavigationPolygon {
+                layer: navigation.layer,
+                vertices,
+                polygons,
+                outlines,
+            })
+        })
+        .collect()
+}
+
+/// Generated atlas images and the tiles in them, with one of each per atlas
+/// source.
+pub struct Atlas {
+    pub images: Vec<RgbaImage>,
+    pub layouts: Vec<Vec<Tile>>,
+    pub tile_size: [u32; 2],
+    pub margins: [u32; 2],
+    pub separation: [u32; 2],
+    pub usage: Vec<AtlasUsage>,
+}
+
+impl Atlas {
+    /// Replaces the tiles in the resource's atlas sources with these layouts,
+    /// and returns the tiles that were replaced, per atlas source. Atlas
+    /// sources are added or removed to match the number of layouts.
+    pub fn apply(&self, resource: &mut TileSetResource) -> Vec<Vec<Tile>> {
+        let removed_sources = resource.set_atlas_source_count(self.layouts.len());
+
+        let mut old_layouts = Vec::new();
+        for (source, layout) in resource.atlas_sources.iter_mut().zip(&self.layouts) {
+            let atlas_source = &mut source.tile_set_atlas_source;
+            atlas_source.margins = Vector2i::from(self.margins);
+            atlas_source.separation = Vector2i::from(self.separation);
+            atlas_source.texture_region_size = Vector2i::from(self.tile_size);
+            old_layouts.push(atlas_source.replace_tiles(layout.clone()));
+        }
+
+        old_layouts.extend(
+            removed_sources
+                .into_iter()
+                .map(|source| source.tile_set_atlas_source.tiles),
+        );
+
+        old_layouts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        fs,
+    };
+
+    use image::{Rgba, RgbaImage};
+
+    use super::{pack_page, Atlas, Grid};
+    use crate::{
+        config::TileSetConfig,
+        godot::{
+            resource::{Tile, TileSetResource},
+            Vector2i,
+        },
+        Project,
+    };
+
+    fn tile_set(properties: &str) -> TileSetConfig {
+        toml::from_str(&format!("tile_size = [16, 16]\n{properties}"))
+            .expect("the tile set config should parse")
+    }
+
+    /// Packs tiles around the occupied cells, and returns the image size and
+    /// the tile positions.
+    fn pack_tiles(
+        tile_set: &TileSetConfig,
+        occupied: &[[u32; 2]],
+        count: usize,
+    ) -> ([u32; 2], Vec<[u32; 2]>) {
+        let grid = Grid {
+            tile_size: tile_set.tile_size,
+            margins: tile_set.margins,
+            separation: tile_set.separation,
+        };
+        let occupied = occupied.iter().copied().collect::<HashSet<_>>();
+
+        pack_page(
+            tile_set,
+            &grid,
+            &occupied,
+            &vec![None; count],
+            occupied.len() + count,
+        )
+        .expect("the tiles should be packed")
+    }
+
+    /// Writes a project with a single terrain, and 2x2 pixel tiles, to a
+    /// temporary directory. The terrain has 16 "match_sides" tiles.
+    fn terrain_project(name: &str, tile_set: &str, tiles: &str) -> Project {
+        let directory =
+            std::env::temp_dir().join(format!("tilecutter-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("terrains")).expect("the directory should be created");
+        fs::create_dir_all(directory.join("tiles")).expect("the directory should be created");
+
+        RgbaImage::from_pixel(2, 8, Rgba([0, 128, 0, 255]))
+            .save(directory.join("terrains/Grass.png"))
+            .expect("the terrain image should be written");
+        RgbaImage::from_pixel(2, 2, Rgba([128, 128, 128, 255]))
+            .save(directory.join("tiles/Wall.png"))
+            .expect("the tile image should be written");
+
+        let config = toml::from_str(&format!(
+            r#"
+[tile_set]
+tile_size = [2, 2]
+tile_shape = "square"
+{tile_set}
+
+[godot]
+project_path = "."
+tile_set_path = "res://tile_set.tres"
+
+{tiles}
+
+[[terrain_sets]]
+terrains = [{{ name = "Grass" }}]
+"#
+        ))
+        .expect("the config should parse");
+
+        Project::new(config, directory).expect("the project should be valid")
+    }
+
+    fn build(project: &Project, previous_layouts: Vec<&[Tile]>) -> Atlas {
+        project
+            .atlas_builder()
+            .previous_layouts(previous_layouts)
+            .build()
+            .expect("the atlas should be built")
+    }
+
+    /// The positions of the terrain tiles, by peering bits.
+    fn terrain_positions(layout: &[Tile]) -> HashMap<String, Vector2i> {
+        layout
+            .iter()
+            .filter(|tile| tile.terrain_set.is_some())
+            .map(|tile| (format!("{:?}", tile.terrains_peering_bit), tile.position))
+            .collect()
+    }
+
+    #[test]
+    fn square_packing() {
+        let (image_size, positions) = pack_tiles(&tile_set(""), &[], 5);
+
+        assert_eq!(image_size, [48, 48]);
+        assert_eq!(positions, [[0, 0], [1, 0], [2, 0], [0, 1], [1, 1]]);
+    }
+
+    #[test]
+    fn tight_packing() {
+        let (image_size, positions) = pack_tiles(&tile_set(r#"packing = "tight""#), &[], 5);
+
+        assert_eq!(image_size, [48, 32]);
+        assert_eq!(positions, [[0, 0], [1, 0], [2, 0], [0, 1], [1, 1]]);
+    }
+
+    #[test]
+    fn column_limits() {
+        let expected_positions = [[0, 0], [1, 0], [0, 1], [1, 1], [0, 2]];
+
+        let (image_size, positions) = pack_tiles(&tile_set("packing = { columns = 2 }"), &[], 5);
+        assert_eq!(image_size, [32, 48]);
+        assert_eq!(positions, expected_positions);
+
+        // A fixed number of columns keeps the width, even when it's not used.
+        let (image_size, positions) = pack_tiles(&tile_set("packing = { columns = 4 }"), &[], 2);
+        assert_eq!(image_size, [64, 16]);
+        assert_eq!(positions, [[0, 0], [1, 0]]);
+
+        let (image_size, positions) = pack_tiles(&tile_set("packing = { max_width = 40 }"), &[], 5);
+        assert_eq!(image_size, [32, 48]);
+        assert_eq!(positions, expected_positions);
+
+        // The margins are on both sides, and the separation is between cells.
+        let (image_size, positions) = pack_tiles(
+            &tile_set("packing = { columns = 2 }\nmargins = [1, 1]\nseparation = [2, 2]"),
+            &[],
+            3,
+        );
+        assert_eq!(image_size, [36, 36]);
+        assert_eq!(positions, [[0, 0], [1, 0], [0, 1]]);
+    }
+
+    #[test]
+    fn power_of_two_sizes() {
+        let (image_size, _) = pack_tiles(
+            &tile_set("packing = { columns = 3 }\npower_of_two = true"),
+            &[],
+            5,
+        );
+        assert_eq!(image_size, [64, 32]);
+
+        // The width limit is rounded down, so the image stays within it.
+        let (image_size, positions) = pack_tiles(
+            &tile_set("packing = { max_width = 60 }\npower_of_two = true"),
+            &[],
+            3,
+        );
+        assert_eq!(image_size, [32, 32]);
+        assert_eq!(positions, [[0, 0], [1, 0], [0, 1]]);
+    }
+
+    #[test]
+    fn occupied_cells_are_skipped() {
+        let (image_size, positions) =
+            pack_tiles(&tile_set("packing = { columns = 2 }"), &[[0, 0], [1, 1]], 3);
+
+        assert_eq!(image_size, [32, 48]);
+        assert_eq!(positions, [[1, 0], [0, 1], [0, 2]]);
+    }
+
+    #[test]
+    fn max_texture_size_limits_the_page() {
+        let (image_size, positions) = pack_tiles(&tile_set("max_texture_size = 40"), &[], 5);
+
+        assert_eq!(image_size, [32, 32]);
+        assert_eq!(positions, [[0, 0], [1, 0], [0, 1], [1, 1]]);
+    }
+
+    #[test]
+    fn previous_positions_are_kept() {
+        let project = terrain_project("previous-positions", "", "");
+        let atlas = build(&project, Vec::new());
+        let layout = &atlas.layouts[0];
+        assert_eq!(layout.len(), 16);
+        assert_eq!(atlas.images[0].dimensions(), (8, 8));
+
+        // The tiles are moved around in the previous layout, as if by hand.
+        let mut previous_layout = layout.clone();
+        let positions = layout.iter().map(|tile| tile.position).collect::<Vec<_>>();
+        for (tile, &position) in previous_layout.iter_mut().zip(positions.iter().rev()) {
+            tile.position = position;
+        }
+
+        let atlas = build(&project, vec![&previous_layout]);
+        assert_eq!(
+            terrain_positions(&atlas.layouts[0]),
+            terrain_positions(&previous_layout)
+        );
+        fs::remove_dir_all(&project.directory).ok();
+
+        // A tile that is put on a terrain tile's cell only moves that tile.
+        let project = terrain_project(
+            "previous-positions-tile",
+            "",
+            "[[tiles]]\nname = \"Wall\"\nposition = [0, 0]",
+        );
+        let atlas = build(&project, vec![&previous_layout]);
+        let mut expected_positions = terrain_positions(&previous_layout);
+        let mut new_positions = terrain_positions(&atlas.layouts[0]);
+
+        expected_positions.retain(|_, position| *position != Vector2i { x: 0, y: 0 });
+        let moved_positions = new_positions
+            .iter()
+            .filter(|(peering_bit, _)| !expected_positions.contains_key(*peering_bit))
+            .map(|(_, &position)| position)
+            .collect::<Vec<_>>();
+        new_positions.retain(|peering_bit, _| expected_positions.contains_key(peering_bit));
+
+        assert_eq!(new_positions, expected_positions);
+        assert_eq!(moved_positions.len(), 1);
+        assert_ne!(moved_positions[0], Vector2i { x: 0, y: 0 });
+        assert!(!expected_positions
+            .values()
+            .any(|&position| position == moved_positions[0]));
+
+        fs::remove_dir_all(&project.directory).ok();
+    }
+
+    #[test]
+    fn atlas_sources_follow_the_pages() {
+        let project = terrain_project("pages", "max_texture_size = 4", "");
+        let atlas = build(&project, Vec::new());
+
+        assert_eq!(atlas.layouts.len(), 4);
+        for (image, layout) in atlas.images.iter().zip(&atlas.layouts) {
+            assert!(image.width() <= 4 && image.height() <= 4);
+            assert_eq!(layout.len(), 4);
+        }
+
+        let mut resource = TileSetResource::new(
+            "uid://tileset".into(),
+            "res://tile_set.png".into(),
+            "uid://texture".into(),
+        );
+        atlas.apply(&mut resource);
+        assert_eq!(resource.atlas_sources.len(), 4);
+        fs::remove_dir_all(&project.directory).ok();
+
+        // Without a limit, all tiles fit in the first atlas source again, and
+        // the others are removed.
+        let project = terrain_project("pages-removed", "", "");
+        let atlas = build(&project, resource.layouts());
+        assert_eq!(atlas.layouts.len(), 1);
+        assert_eq!(atlas.layouts[0].len(), 16);
+
+        let old_layouts = atlas.apply(&mut resource);
+        assert_eq!(resource.atlas_sources.len(), 1);
+        assert_eq!(old_layouts.len(), 4);
+        assert_eq!(old_layouts.iter().map(Vec::len).sum::<usize>(), 16);
+
+        let mut output = Vec::new();
+        resource
+            .print(&mut output, &project.config)
+            .expect("the resource should be printed");
+        let output = String::from_utf8(output).expect("the output should be UTF-8");
+        assert_eq!(output.matches("\"TileSetAtlasSource\"").count(), 1);
+        assert_eq!(output.matches("\"Texture2D\"").count(), 1);
+        assert_eq!(output.matches("sources/").count(), 1);
+
+        fs::remove_dir_all(&project.directory).ok();
+    }
+}
@@ -1,39 +1,361 @@
+use std::collections::BTreeMap;
+
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct Config {
+pub struct Config {
     pub tile_set: TileSetConfig,
     pub godot: GodotConfig,
     #[serde(default)]
     pub tiles: Vec<TileConfig>,
     #[serde(default)]
     pub terrain_sets: Vec<TerrainSetConfig>,
+    #[serde(default)]
+    pub physics_layers: Vec<PhysicsLayerConfig>,
+    #[serde(default)]
+    pub occlusion_layers: Vec<OcclusionLayerConfig>,
+    #[serde(default)]
+    pub navigation_layers: Vec<NavigationLayerConfig>,
+    #[serde(default)]
+    pub custom_data_layers: Vec<CustomDataLayerConfig>,
 }
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct TileSetConfig {
+pub struct TileSetConfig {
     pub tile_size: [u32; 2],
+    #[serde(default)]
+    pub tile_shape: TileShape,
+    #[serde(default)]
+    pub tile_layout: TileLayout,
+    #[serde(default)]
+    pub tile_offset_axis: TileOffsetAxis,
+    /// The number of pixels around the tiles in the atlas image.
+    #[serde(default)]
+    pub margins: [u32; 2],
+    /// The number of pixels between the tiles in the atlas image.
+    #[serde(default)]
+    pub separation: [u32; 2],
+    /// The number of pixels that the edges of the tiles are repeated into the
+    /// margins and separation, to stop neighboring tiles from bleeding into
+    /// each other.
+    #[serde(default)]
+    pub extrusion: u32,
+    #[serde(default)]
+    pub packing: Packing,
+    /// Rounds the width and height of the atlas image up to powers of two.
+    #[serde(default)]
+    pub power_of_two: bool,
+    /// The largest width and height of an atlas image, in pixels. Terrain
+    /// tiles that don't fit are put in additional atlas sources, with their
+    /// own images.
+    pub max_texture_size: Option<u32>,
+}
+
+/// How the atlas image is sized to fit the tiles. Terrain tiles that don't
+/// have positions from the previous export are placed row by row.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Packing {
+    /// A square image that grows one tile at a time.
+    #[default]
+    Square,
+    /// A rectangle that is close to square, and cropped to the used cells.
+    Tight,
+    /// A fixed number of columns.
+    Columns(u32),
+    /// As many columns as fit in a width, in pixels.
+    MaxWidth(u32),
+}
+
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum TileShape {
+    Square,
+    Isometric,
+    HalfOffsetSquare,
+    #[default]
+    Hexagon,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TileLayout {
+    #[default]
+    Stacked,
+    StackedOffset,
+    StairsRight,
+    StairsDown,
+    DiamondRight,
+    DiamondDown,
+}
+
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum TileOffsetAxis {
+    Horizontal,
+    #[default]
+    Vertical,
 }
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct GodotConfig {
+pub struct GodotConfig {
     pub project_path: String,
     pub tile_set_path: String,
 }
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct TileConfig {
+pub struct TileConfig {
     pub name: String,
     pub position: [u32; 2],
+    /// The number of atlas cells the tile covers, starting at `position`. The
+    /// image includes the separation between the cells.
+    #[serde(default = "default_tile_size_in_atlas")]
+    pub size: [u32; 2],
+    #[serde(default)]
+    pub collision: Vec<CollisionConfig>,
+    #[serde(default)]
+    pub navigation: Vec<NavigationConfig>,
+    /// Custom data values, by custom data layer name.
+    #[serde(default)]
+    pub custom_data: BTreeMap<String, toml::Value>,
+    /// Makes the tile image a horizontal or vertical strip of frames.
+    pub animation: Option<AnimationConfig>,
+    #[serde(default)]
+    pub alternatives: Vec<AlternativeConfig>,
+    #[serde(flatten)]
+    pub rendering: RenderingConfig,
 }
 
-#[derive(Deserialize, Debug)]
-pub(crate) struct TerrainSetConfig {
+fn default_tile_size_in_atlas() -> [u32; 2] {
+    [1, 1]
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct TerrainSetConfig {
+    #[serde(default)]
+    pub mode: TerrainMode,
+    /// A mask image in the `terrains` directory, with a color per sector.
+    /// The mask is generated from the tile shape if it isn't set.
+    pub mask: Option<String>,
+    /// Angles for the generated mask, in degrees clockwise from the right, by
+    /// neighbor name. Each sector spans from one neighbor's angle to the next,
+    /// and the angles default to the directions of the neighbors.
+    #[serde(default)]
+    pub mask_angles: BTreeMap<String, f64>,
+    /// The radius, in pixels, that the edges between the mask sectors are
+    /// softened by. The terrain images are blended in linear color space
+    /// across the edges.
+    #[serde(default)]
+    pub feather: f64,
+    /// Makes the terrain images have the frames side by side. All tiles in the
+    /// set have the same frames.
+    pub animation: Option<AnimationConfig>,
     #[serde(default)]
     pub terrains: Vec<TerrainConfig>,
 }
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct TerrainConfig {
+pub struct AnimationConfig {
+    /// The duration of each frame, in seconds.
+    pub frames: Vec<f64>,
+    #[serde(default = "default_animation_speed")]
+    pub speed: f64,
+    /// The number of frames per row in the atlas, or 0 to put them all in one
+    /// row.
+    #[serde(default)]
+    pub columns: u32,
+    /// The number of cells between the frames in the atlas.
+    #[serde(default)]
+    pub separation: [u32; 2],
+}
+
+fn default_animation_speed() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TerrainMode {
+    #[serde(rename = "match_corners_and_sides")]
+    CornersAndSides,
+    #[serde(rename = "match_corners")]
+    Corners,
+    #[serde(rename = "match_sides")]
+    #[default]
+    Sides,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TerrainConfig {
+    pub name: String,
+    /// Collision polygons for all tiles with this terrain.
+    #[serde(default)]
+    pub collision: Vec<CollisionConfig>,
+    /// Navigation polygons for all tiles with this terrain.
+    #[serde(default)]
+    pub navigation: Vec<NavigationConfig>,
+    /// Custom data values for all tiles with this terrain, by custom data
+    /// layer name.
+    #[serde(default)]
+    pub custom_data: BTreeMap<String, toml::Value>,
+    /// The probability weights of the terrain's variant images, where index 0
+    /// is `Name.png` and index N is `Name.N.png`. Missing weights are 1.0.
+    #[serde(default)]
+    pub variant_weights: Vec<f64>,
+    /// Alternatives for all tiles with this terrain in the center.
+    #[serde(default)]
+    pub alternatives: Vec<AlternativeConfig>,
+    /// Rendering properties for all tiles with this terrain in the center.
+    #[serde(flatten)]
+    pub rendering: RenderingConfig,
+}
+
+/// Tile rendering properties. They are left as they are in the tile set when
+/// they aren't set.
+#[derive(Deserialize, Debug, Default)]
+pub struct RenderingConfig {
+    /// The point in the tile image, in pixels from its center, that is drawn
+    /// at the center of the cell.
+    pub texture_origin: Option<[i32; 2]>,
+    pub z_index: Option<i32>,
+    /// The Y position, in pixels from the center of the cell, that the tile is
+    /// sorted by.
+    pub y_sort_origin: Option<i32>,
+    /// A material on the format `res://Path/To/material.tres`.
+    pub material: Option<String>,
+}
+
+/// An alternative version of a tile, which uses the same atlas cells. Terrain
+/// peering bits are mirrored to match the flips.
+#[derive(Deserialize, Debug)]
+pub struct AlternativeConfig {
+    #[serde(default)]
+    pub flip_h: bool,
+    #[serde(default)]
+    pub flip_v: bool,
+    /// Swaps the X and Y axes, before flipping.
+    #[serde(default)]
+    pub transpose: bool,
+    /// A color to multiply the tile with, as three or four numbers between 0
+    /// and 1.
+    pub modulate: Option<Vec<f64>>,
+    /// The probability weight of the alternative. Defaults to the weight of
+    /// the tile.
+    pub probability: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PhysicsLayerConfig {
+    #[serde(default = "default_collision_bits")]
+    pub collision_layer: u32,
+    #[serde(default = "default_collision_bits")]
+    pub collision_mask: u32,
+}
+
+fn default_collision_bits() -> u32 {
+    1
+}
+
+/// An occlusion layer, where all tiles get an occluder that is traced from
+/// their opaque pixels.
+#[derive(Deserialize, Debug)]
+pub struct OcclusionLayerConfig {
+    #[serde(default = "default_light_mask")]
+    pub light_mask: u32,
+    #[serde(default)]
+    pub sdf_collision: bool,
+    /// How far, in pixels, the occluders may be from the outlines of the tiles.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+}
+
+fn default_light_mask() -> u32 {
+    1
+}
+
+/// How far, in pixels, a simplified outline may be from the traced one, unless
+/// something else is configured.
+pub(crate) const DEFAULT_TOLERANCE: f64 = 1.0;
+
+fn default_tolerance() -> f64 {
+    DEFAULT_TOLERANCE
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CollisionConfig {
+    /// The index of the physics layer.
+    #[serde(default)]
+    pub layer: usize,
+    pub polygons: PolygonsConfig,
+}
+
+/// Polygons that are either traced from the opaque pixels of a tile, or listed
+/// as points in the tile image's pixel coordinates.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PolygonsConfig {
+    Auto(AutoPolygons),
+    Points(Vec<Vec<[f64; 2]>>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoPolygons {
+    Auto,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NavigationLayerConfig {
+    /// The navigation layers bitmask of the layer's regions.
+    #[serde(default = "default_navigation_layers")]
+    pub layers: u32,
+}
+
+fn default_navigation_layers() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NavigationConfig {
+    /// The index of the navigation layer.
+    #[serde(default)]
+    pub layer: usize,
+    pub area: NavigationArea,
+}
+
+/// The walkable part of a tile.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NavigationArea {
+    /// The whole cell is walkable.
+    Cell,
+    /// The cell is walkable, except where the tile image is opaque.
+    Transparent,
+    /// Nothing is walkable.
+    None,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CustomDataLayerConfig {
     pub name: String,
+    #[serde(rename = "type")]
+    pub ty: CustomDataType,
+}
+
+/// The Variant type of a custom data layer.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomDataType {
+    Bool,
+    Int,
+    Float,
+    String,
+    StringName,
+    /// An array of two numbers.
+    Vector2,
+    /// An array of two integers.
+    Vector2i,
+    /// An array of three or four numbers between 0 and 1.
+    Color,
 }
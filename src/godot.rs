@@ -1,4 +1,7 @@
 mod godot_file;
-pub(crate) mod resource;
+pub mod resource;
+mod uid;
 
-pub(crate) use godot_file::{parse_file, Vector2i};
+pub(crate) use godot_file::parse_file;
+pub use godot_file::Vector2i;
+pub use uid::generate_uid;
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     fs::File,
     io::{self, BufReader, Bytes, Read, Write},
     path::Path,
@@ -13,10 +14,16 @@ where
     P: AsRef<Path>,
 {
     let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    parse(file)
+}
+
+fn parse(reader: impl Read + 'static) -> Result<GodotFile> {
+    let reader: Box<dyn Read> = Box::new(reader);
+    let reader = BufReader::new(reader);
     let mut tokens = Tokenizer {
         bytes: reader.bytes(),
         saved: None,
+        saved_token: None,
     };
 
     let Some(header) = Tag::parse(&mut tokens).context("could not parse header tag")? else {
@@ -53,6 +60,7 @@ pub(crate) struct GodotFile {
     pub tags: Vec<Tag>,
 }
 
+#[derive(Debug, Clone, Default)]
 pub(crate) struct Tag {
     pub name: String,
     pub fields: Vec<Field>,
@@ -60,6 +68,14 @@ pub(crate) struct Tag {
 }
 
 impl Tag {
+    /// Finds a field with a string value.
+    pub(crate) fn string_field(&self, identifier: &str) -> Option<&str> {
+        self.fields.iter().find_map(|field| match &field.value {
+            Value::String(value) if field.identifier == identifier => Some(&**value),
+            _ => None,
+        })
+    }
+
     fn parse(tokens: &mut Tokenizer) -> Result<Option<Self>> {
         match tokens.next_token()? {
             Some(Token::BracketOpen) => {}
@@ -131,18 +147,20 @@ impl GodotFmt for Tag {
         for assign in &self.assigns {
             write!(w, "{} = ", assign.assign)?;
             assign.value.godot_fmt(w)?;
-            writeln!(w, "")?;
+            writeln!(w)?;
         }
 
         Ok(())
     }
 }
 
+#[derive(Debug, Clone)]
 pub(crate) struct Field {
     pub identifier: String,
     pub value: Value,
 }
 
+#[derive(Debug, Clone)]
 pub(crate) struct TagAssign {
     pub assign: String,
     pub value: Value,
@@ -191,7 +209,7 @@ impl TagAssign {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum Value {
     Bool(bool),
     Null,
@@ -199,109 +217,342 @@ pub(crate) enum Value {
     Double(f64),
     String(String),
     StringName(String),
+    NodePath(String),
     Color(Color),
     Vector2i(Vector2i),
+    /// A built-in type with float components, such as `Vector2(1, 2)`.
+    Floats(FloatType, Vec<f64>),
+    /// A built-in type with integer components, such as `Rect2i(0, 0, 1, 1)`.
+    Integers(IntegerType, Vec<i64>),
+    /// A `PackedByteArray` in its base64 encoded form.
+    PackedByteArrayBase64(String),
+    PackedStringArray(Vec<String>),
+    Array(Vec<Value>),
+    TypedArray(ElementType, Vec<Value>),
+    Dictionary(Vec<(Value, Value)>),
+    TypedDictionary(ElementType, ElementType, Vec<(Value, Value)>),
+    Object(String, Vec<(String, Value)>),
+    Callable,
+    Signal,
+    Resource(String),
     SubResource(String),
     ExtResource(String),
 }
 
 impl Value {
+    /// Adds the IDs of the sub-resources and external resources that are used
+    /// in this value.
+    pub(crate) fn collect_resource_ids(
+        &self,
+        sub_resources: &mut HashSet<String>,
+        ext_resources: &mut HashSet<String>,
+    ) {
+        match self {
+            Value::SubResource(id) => {
+                sub_resources.insert(id.clone());
+            }
+            Value::ExtResource(id) => {
+                ext_resources.insert(id.clone());
+            }
+            Value::Array(values) | Value::TypedArray(_, values) => {
+                for value in values {
+                    value.collect_resource_ids(sub_resources, ext_resources);
+                }
+            }
+            Value::Dictionary(entries) | Value::TypedDictionary(_, _, entries) => {
+                for (key, value) in entries {
+                    key.collect_resource_ids(sub_resources, ext_resources);
+                    value.collect_resource_ids(sub_resources, ext_resources);
+                }
+            }
+            Value::Object(_, properties) => {
+                for (_, value) in properties {
+                    value.collect_resource_ids(sub_resources, ext_resources);
+                }
+            }
+            _ => {}
+        }
+    }
+
     fn parse(tokens: &mut Tokenizer) -> Result<Self> {
         match tokens.next_token()? {
-            Some(Token::Identifier(id)) => match &*id {
-                "true" => Ok(Self::Bool(true)),
-                "false" => Ok(Self::Bool(false)),
-                "null" | "nil" => Ok(Self::Null),
-                "inf" => Ok(Self::Double(f64::INFINITY)),
-                "neg_inf" => Ok(Self::Double(f64::NEG_INFINITY)),
-                "nan" => Ok(Self::Double(f64::NAN)),
-                "Vector2i" => {
-                    let args = Self::parse_int_constructor(tokens)?;
-
-                    let [x, y] = *args else {
-                        bail!("Vector2i requires 2 arguments");
-                    };
+            Some(token) => Self::parse_token(token, tokens),
+            None => bail!("expected a value, but found end of file"),
+        }
+    }
+
+    /// Parses a value that begins with an already read token.
+    fn parse_token(token: Token, tokens: &mut Tokenizer) -> Result<Self> {
+        match token {
+            Token::Identifier(id) => Self::parse_identifier(id, tokens),
+            Token::BracketOpen => Ok(Self::Array(Self::parse_array(tokens)?)),
+            Token::CurlyBracketOpen => Ok(Self::Dictionary(Self::parse_dictionary(tokens)?)),
+            Token::Integer(value) => Ok(Self::Integer(value)),
+            Token::Double(value) => Ok(Self::Double(value)),
+            Token::String(value) => Ok(Self::String(value)),
+            Token::StringName(value) => Ok(Self::StringName(value)),
+            Token::NodePath(value) => Ok(Self::NodePath(value)),
+            Token::Color(value) => Ok(Self::Color(Color::Html(value))),
+            token => bail!("unsupported or unexpected value token {token:?}"),
+        }
+    }
 
-                    Ok(Self::Vector2i(Vector2i { x, y }))
+    fn parse_identifier(id: String, tokens: &mut Tokenizer) -> Result<Self> {
+        if let Some(ty) = FloatType::from_name(&id) {
+            let args = Self::parse_double_constructor(tokens)?;
+            ty.check_len(args.len())?;
+
+            return Ok(Self::Floats(ty, args));
+        }
+
+        if let Some(ty) = IntegerType::from_name(&id) {
+            if ty == IntegerType::PackedByteArray {
+                tokens.expect(Token::ParenthesisOpen)?;
+
+                match tokens.next_token()? {
+                    Some(Token::String(value)) => {
+                        tokens.expect(Token::ParenthesisClose)?;
+                        return Ok(Self::PackedByteArrayBase64(value));
+                    }
+                    Some(token) => tokens.save_token(token),
+                    None => bail!("expected a PackedByteArray() argument"),
                 }
-                "Color" => {
-                    let args = Self::parse_double_constructor(tokens)?;
 
-                    let [r, g, b, a] = *args else {
-                        bail!("Color requires 4 arguments");
+                let args = Self::parse_int_arguments(tokens)?;
+                return Ok(Self::Integers(ty, args));
+            }
+
+            let args = Self::parse_int_constructor(tokens)?;
+            ty.check_len(args.len())?;
+
+            return Ok(Self::Integers(ty, args));
+        }
+
+        match &*id {
+            "true" => Ok(Self::Bool(true)),
+            "false" => Ok(Self::Bool(false)),
+            "null" | "nil" => Ok(Self::Null),
+            "inf" => Ok(Self::Double(f64::INFINITY)),
+            "inf_neg" | "neg_inf" | "-inf" => Ok(Self::Double(f64::NEG_INFINITY)),
+            "nan" => Ok(Self::Double(f64::NAN)),
+            "Vector2i" => {
+                let args = Self::parse_int_constructor(tokens)?;
+
+                let [x, y] = *args else {
+                    bail!("Vector2i requires 2 arguments");
+                };
+
+                Ok(Self::Vector2i(Vector2i { x, y }))
+            }
+            "Color" => {
+                let args = Self::parse_double_constructor(tokens)?;
+
+                let [r, g, b, a] = *args else {
+                    bail!("Color requires 4 arguments");
+                };
+
+                Ok(Self::Color(Color::Rgba(r, g, b, a)))
+            }
+            "PackedStringArray" => {
+                tokens.expect(Token::ParenthesisOpen)?;
+                let mut strings = Vec::new();
+
+                loop {
+                    let token = match tokens.next_token()? {
+                        Some(Token::ParenthesisClose) => break,
+                        Some(Token::Comma) if !strings.is_empty() => tokens.next_token()?,
+                        token if strings.is_empty() => token,
+                        Some(token) => bail!("expected ',' or ')', but found {token:?}"),
+                        None => bail!("expected ',' or ')'"),
                     };
 
-                    Ok(Self::Color(Color::Rgba(r, g, b, a)))
+                    match token {
+                        Some(Token::String(value)) => strings.push(value),
+                        Some(Token::ParenthesisClose) => break,
+                        Some(token) => bail!("expected string, but found {token:?}"),
+                        None => bail!("expected string"),
+                    }
                 }
-                "SubResource" => {
-                    match tokens.next_token()? {
-                        Some(Token::ParenthesisOpen) => {}
-                        Some(token) => bail!("expected '(', but found {:?}", token),
-                        None => bail!("expected '('"),
-                    };
 
-                    let value = match tokens.next_token()? {
-                        Some(Token::String(value)) => value,
-                        Some(token) => bail!(
-                            "expected a string argument to SubResource(), but found {:?}",
-                            token
-                        ),
-                        None => bail!("expected a string argument to SubResource()"),
-                    };
+                Ok(Self::PackedStringArray(strings))
+            }
+            "Array" => match tokens.next_token()? {
+                Some(Token::BracketOpen) => {
+                    let element_type = ElementType::parse(tokens)?;
+                    tokens.expect(Token::BracketClose)?;
+                    tokens.expect(Token::ParenthesisOpen)?;
+                    tokens.expect(Token::BracketOpen)?;
+                    let values = Self::parse_array(tokens)?;
+                    tokens.expect(Token::ParenthesisClose)?;
+
+                    Ok(Self::TypedArray(element_type, values))
+                }
+                Some(Token::ParenthesisOpen) => {
+                    tokens.expect(Token::BracketOpen)?;
+                    let values = Self::parse_array(tokens)?;
+                    tokens.expect(Token::ParenthesisClose)?;
 
-                    match tokens.next_token()? {
-                        Some(Token::ParenthesisClose) => {}
-                        Some(token) => bail!("expected ')', but found {:?}", token),
-                        None => bail!("expected ')'"),
-                    };
+                    Ok(Self::Array(values))
+                }
+                Some(token) => bail!("expected '[' or '(', but found {token:?}"),
+                None => bail!("expected '[' or '('"),
+            },
+            "Dictionary" => match tokens.next_token()? {
+                Some(Token::BracketOpen) => {
+                    let key_type = ElementType::parse(tokens)?;
+                    tokens.expect(Token::Comma)?;
+                    let value_type = ElementType::parse(tokens)?;
+                    tokens.expect(Token::BracketClose)?;
+                    tokens.expect(Token::ParenthesisOpen)?;
+                    tokens.expect(Token::CurlyBracketOpen)?;
+                    let entries = Self::parse_dictionary(tokens)?;
+                    tokens.expect(Token::ParenthesisClose)?;
+
+                    Ok(Self::TypedDictionary(key_type, value_type, entries))
+                }
+                Some(Token::ParenthesisOpen) => {
+                    tokens.expect(Token::CurlyBracketOpen)?;
+                    let entries = Self::parse_dictionary(tokens)?;
+                    tokens.expect(Token::ParenthesisClose)?;
 
-                    Ok(Self::SubResource(value))
+                    Ok(Self::Dictionary(entries))
                 }
-                "ExtResource" => {
-                    match tokens.next_token()? {
-                        Some(Token::ParenthesisOpen) => {}
-                        Some(token) => bail!("expected '(', but found {:?}", token),
-                        None => bail!("expected '('"),
-                    };
+                Some(token) => bail!("expected '[' or '(', but found {token:?}"),
+                None => bail!("expected '[' or '('"),
+            },
+            "Object" => {
+                tokens.expect(Token::ParenthesisOpen)?;
 
-                    let value = match tokens.next_token()? {
-                        Some(Token::String(value)) => value,
-                        Some(token) => bail!(
-                            "expected a string argument to ExtResource(), but found {:?}",
-                            token
-                        ),
-                        None => bail!("expected a string argument to ExtResource()"),
-                    };
+                let class = match tokens.next_token()? {
+                    Some(Token::Identifier(class)) => class,
+                    Some(token) => bail!("expected a class name, but found {token:?}"),
+                    None => bail!("expected a class name"),
+                };
+
+                let mut properties = Vec::new();
 
+                loop {
                     match tokens.next_token()? {
-                        Some(Token::ParenthesisClose) => {}
-                        Some(token) => bail!("expected ')', but found {:?}", token),
-                        None => bail!("expected ')'"),
+                        Some(Token::Comma) => {}
+                        Some(Token::ParenthesisClose) => break,
+                        Some(token) => bail!("expected ',' or ')', but found {token:?}"),
+                        None => bail!("expected ',' or ')'"),
+                    }
+
+                    let name = match tokens.next_token()? {
+                        Some(Token::String(name)) => name,
+                        Some(Token::ParenthesisClose) => break,
+                        Some(token) => bail!("expected a property name, but found {token:?}"),
+                        None => bail!("expected a property name"),
                     };
 
-                    Ok(Self::ExtResource(value))
+                    tokens.expect(Token::Colon)?;
+                    properties.push((name, Self::parse(tokens)?));
                 }
-                _ => bail!("unsupported or unexpected value identifier '{id}'"),
-            },
-            Some(Token::Integer(value)) => Ok(Self::Integer(value)),
-            Some(Token::Double(value)) => Ok(Self::Double(value)),
-            Some(Token::String(value)) => Ok(Self::String(value)),
-            Some(Token::StringName(value)) => Ok(Self::StringName(value)),
-            Some(Token::Color(value)) => Ok(Self::Color(Color::Html(value))),
-            Some(token) => bail!("unsupported or unexpected value token {token:?}"),
-            None => bail!("expected a value, but found end of file"),
+
+                Ok(Self::Object(class, properties))
+            }
+            "Callable" => {
+                tokens.expect(Token::ParenthesisOpen)?;
+                tokens.expect(Token::ParenthesisClose)?;
+                Ok(Self::Callable)
+            }
+            "Signal" => {
+                tokens.expect(Token::ParenthesisOpen)?;
+                tokens.expect(Token::ParenthesisClose)?;
+                Ok(Self::Signal)
+            }
+            "NodePath" => Ok(Self::NodePath(Self::parse_string_constructor(
+                tokens, "NodePath",
+            )?)),
+            "Resource" => Ok(Self::Resource(Self::parse_string_constructor(
+                tokens, "Resource",
+            )?)),
+            "SubResource" => Ok(Self::SubResource(Self::parse_string_constructor(
+                tokens,
+                "SubResource",
+            )?)),
+            "ExtResource" => Ok(Self::ExtResource(Self::parse_string_constructor(
+                tokens,
+                "ExtResource",
+            )?)),
+            _ => bail!("unsupported or unexpected value identifier '{id}'"),
         }
     }
 
-    fn parse_int_constructor(tokens: &mut Tokenizer) -> Result<Vec<i64>> {
-        let mut args = Vec::new();
+    /// Parses the values of an array, after the opening `[`.
+    fn parse_array(tokens: &mut Tokenizer) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
 
-        match tokens.next_token()? {
-            Some(Token::ParenthesisOpen) => {}
-            Some(token) => bail!("expected '(', but found {:?}", token),
-            None => bail!("expected '('"),
+        loop {
+            let token = match tokens.next_token()? {
+                Some(Token::BracketClose) => break,
+                Some(Token::Comma) if !values.is_empty() => match tokens.next_token()? {
+                    Some(Token::BracketClose) => break,
+                    Some(token) => token,
+                    None => bail!("unexpected end of file in array"),
+                },
+                Some(token) if values.is_empty() => token,
+                Some(token) => bail!("expected ',' or ']', but found {token:?}"),
+                None => bail!("unexpected end of file in array"),
+            };
+
+            values.push(Self::parse_token(token, tokens)?);
+        }
+
+        Ok(values)
+    }
+
+    /// Parses the entries of a dictionary, after the opening `{`.
+    fn parse_dictionary(tokens: &mut Tokenizer) -> Result<Vec<(Value, Value)>> {
+        let mut entries = Vec::new();
+
+        loop {
+            let token = match tokens.next_token()? {
+                Some(Token::CurlyBracketClose) => break,
+                Some(Token::Comma) if !entries.is_empty() => match tokens.next_token()? {
+                    Some(Token::CurlyBracketClose) => break,
+                    Some(token) => token,
+                    None => bail!("unexpected end of file in dictionary"),
+                },
+                Some(token) if entries.is_empty() => token,
+                Some(token) => bail!("expected ',' or '}}', but found {token:?}"),
+                None => bail!("unexpected end of file in dictionary"),
+            };
+
+            let key = Self::parse_token(token, tokens)?;
+            tokens.expect(Token::Colon)?;
+            let value = Self::parse(tokens)?;
+
+            entries.push((key, value));
+        }
+
+        Ok(entries)
+    }
+
+    fn parse_string_constructor(tokens: &mut Tokenizer, name: &str) -> Result<String> {
+        tokens.expect(Token::ParenthesisOpen)?;
+
+        let value = match tokens.next_token()? {
+            Some(Token::String(value)) => value,
+            Some(token) => bail!("expected a string argument to {name}(), but found {token:?}"),
+            None => bail!("expected a string argument to {name}()"),
         };
 
+        tokens.expect(Token::ParenthesisClose)?;
+
+        Ok(value)
+    }
+
+    fn parse_int_constructor(tokens: &mut Tokenizer) -> Result<Vec<i64>> {
+        tokens.expect(Token::ParenthesisOpen)?;
+        Self::parse_int_arguments(tokens)
+    }
+
+    /// Parses integer arguments, after the opening `(`.
+    fn parse_int_arguments(tokens: &mut Tokenizer) -> Result<Vec<i64>> {
+        let mut args = Vec::new();
+
         loop {
             if !args.is_empty() {
                 match tokens.next_token()? {
@@ -328,11 +579,7 @@ impl Value {
     fn parse_double_constructor(tokens: &mut Tokenizer) -> Result<Vec<f64>> {
         let mut args = Vec::new();
 
-        match tokens.next_token()? {
-            Some(Token::ParenthesisOpen) => {}
-            Some(token) => bail!("expected '(', but found {:?}", token),
-            None => bail!("expected '('"),
-        };
+        tokens.expect(Token::ParenthesisOpen)?;
 
         loop {
             if !args.is_empty() {
@@ -347,6 +594,12 @@ impl Value {
             let value = match tokens.next_token()? {
                 Some(Token::Integer(value)) => value as f64,
                 Some(Token::Double(value)) => value,
+                Some(Token::Identifier(id)) => match &*id {
+                    "inf" => f64::INFINITY,
+                    "inf_neg" | "neg_inf" | "-inf" => f64::NEG_INFINITY,
+                    "nan" => f64::NAN,
+                    _ => bail!("expected float, but found '{id}'"),
+                },
                 Some(Token::ParenthesisClose) if args.is_empty() => break,
                 Some(token) => bail!("expected float, but found {:?}", token),
                 None => bail!("expected float"),
@@ -370,26 +623,301 @@ impl GodotFmt for Value {
                 let mut string = Vec::new();
                 value.godot_fmt(&mut string)?;
 
-                if string != b"inf" && string != b"inf_neg" && string != b"nan" {
-                    if !string.contains(&b'.') && !string.contains(&b'e') {
-                        string.extend_from_slice(b".0");
-                    }
+                if string != b"inf"
+                    && string != b"inf_neg"
+                    && string != b"nan"
+                    && !string.contains(&b'.')
+                    && !string.contains(&b'e')
+                {
+                    string.extend_from_slice(b".0");
                 }
 
                 w.write_all(&string)
             }
             Value::String(value) => write!(w, r#""{value}""#),
             Value::StringName(value) => write!(w, r#"&"{value}""#),
+            Value::NodePath(value) => write!(w, r#"NodePath("{value}")"#),
             Value::Color(value) => value.godot_fmt(w),
             Value::Vector2i(value) => value.godot_fmt(w),
+            Value::Floats(ty, values) => {
+                write!(w, "{}(", ty.name())?;
+                write_separated(w, values, |w, value| value.godot_fmt(w))?;
+                write!(w, ")")
+            }
+            Value::Integers(ty, values) => {
+                write!(w, "{}(", ty.name())?;
+                write_separated(w, values, |w, value| value.godot_fmt(w))?;
+                write!(w, ")")
+            }
+            Value::PackedByteArrayBase64(value) => write!(w, r#"PackedByteArray("{value}")"#),
+            Value::PackedStringArray(values) => {
+                write!(w, "PackedStringArray(")?;
+                write_separated(w, values, |w, value| write!(w, r#""{value}""#))?;
+                write!(w, ")")
+            }
+            Value::Array(values) => write_array(w, values),
+            Value::TypedArray(element_type, values) => {
+                write!(w, "Array[")?;
+                element_type.godot_fmt(w)?;
+                write!(w, "](")?;
+                write_array(w, values)?;
+                write!(w, ")")
+            }
+            Value::Dictionary(entries) => write_dictionary(w, entries),
+            Value::TypedDictionary(key_type, value_type, entries) => {
+                write!(w, "Dictionary[")?;
+                key_type.godot_fmt(w)?;
+                write!(w, ", ")?;
+                value_type.godot_fmt(w)?;
+                write!(w, "](")?;
+                write_dictionary(w, entries)?;
+                write!(w, ")")
+            }
+            Value::Object(class, properties) => {
+                write!(w, "Object({class}")?;
+                for (name, value) in properties {
+                    write!(w, r#","{name}":"#)?;
+                    value.godot_fmt(w)?;
+                }
+                write!(w, ")")
+            }
+            Value::Callable => write!(w, "Callable()"),
+            Value::Signal => write!(w, "Signal()"),
+            Value::Resource(value) => write!(w, r#"Resource("{value}")"#),
             Value::SubResource(value) => write!(w, r#"SubResource("{value}")"#),
             Value::ExtResource(value) => write!(w, r#"ExtResource("{value}")"#),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct Vector2i {
+fn write_separated<T>(
+    w: &mut dyn Write,
+    values: &[T],
+    mut write_value: impl FnMut(&mut dyn Write, &T) -> io::Result<()>,
+) -> io::Result<()> {
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            write!(w, ", ")?;
+        }
+
+        write_value(w, value)?;
+    }
+
+    Ok(())
+}
+
+fn write_array(w: &mut dyn Write, values: &[Value]) -> io::Result<()> {
+    write!(w, "[")?;
+    write_separated(w, values, |w, value| value.godot_fmt(w))?;
+    write!(w, "]")
+}
+
+fn write_dictionary(w: &mut dyn Write, entries: &[(Value, Value)]) -> io::Result<()> {
+    if entries.is_empty() {
+        return write!(w, "{{}}");
+    }
+
+    // Godot writes each entry on its own line.
+    writeln!(w, "{{")?;
+    for (index, (key, value)) in entries.iter().enumerate() {
+        if index > 0 {
+            writeln!(w, ",")?;
+        }
+
+        key.godot_fmt(w)?;
+        write!(w, ": ")?;
+        value.godot_fmt(w)?;
+    }
+    write!(w, "\n}}")
+}
+
+/// Built-in types that are written as a constructor with float arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FloatType {
+    Vector2,
+    Vector3,
+    Vector4,
+    Rect2,
+    Transform2D,
+    Plane,
+    Quaternion,
+    Aabb,
+    Basis,
+    Transform3D,
+    Projection,
+    PackedFloat32Array,
+    PackedFloat64Array,
+    PackedVector2Array,
+    PackedVector3Array,
+    PackedVector4Array,
+    PackedColorArray,
+}
+
+impl FloatType {
+    const ALL: [Self; 17] = [
+        Self::Vector2,
+        Self::Vector3,
+        Self::Vector4,
+        Self::Rect2,
+        Self::Transform2D,
+        Self::Plane,
+        Self::Quaternion,
+        Self::Aabb,
+        Self::Basis,
+        Self::Transform3D,
+        Self::Projection,
+        Self::PackedFloat32Array,
+        Self::PackedFloat64Array,
+        Self::PackedVector2Array,
+        Self::PackedVector3Array,
+        Self::PackedVector4Array,
+        Self::PackedColorArray,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Vector2 => "Vector2",
+            Self::Vector3 => "Vector3",
+            Self::Vector4 => "Vector4",
+            Self::Rect2 => "Rect2",
+            Self::Transform2D => "Transform2D",
+            Self::Plane => "Plane",
+            Self::Quaternion => "Quaternion",
+            Self::Aabb => "AABB",
+            Self::Basis => "Basis",
+            Self::Transform3D => "Transform3D",
+            Self::Projection => "Projection",
+            Self::PackedFloat32Array => "PackedFloat32Array",
+            Self::PackedFloat64Array => "PackedFloat64Array",
+            Self::PackedVector2Array => "PackedVector2Array",
+            Self::PackedVector3Array => "PackedVector3Array",
+            Self::PackedVector4Array => "PackedVector4Array",
+            Self::PackedColorArray => "PackedColorArray",
+        }
+    }
+
+    /// Checks the number of arguments. Packed arrays take any multiple of
+    /// their element size.
+    fn check_len(self, len: usize) -> Result<()> {
+        let (expected, is_packed) = match self {
+            Self::Vector2 => (2, false),
+            Self::Vector3 => (3, false),
+            Self::Vector4 | Self::Rect2 | Self::Plane | Self::Quaternion => (4, false),
+            Self::Transform2D | Self::Aabb => (6, false),
+            Self::Basis => (9, false),
+            Self::Transform3D => (12, false),
+            Self::Projection => (16, false),
+            Self::PackedFloat32Array | Self::PackedFloat64Array => (1, true),
+            Self::PackedVector2Array => (2, true),
+            Self::PackedVector3Array => (3, true),
+            Self::PackedVector4Array | Self::PackedColorArray => (4, true),
+        };
+
+        if is_packed && len % expected != 0 {
+            bail!(
+                "{} requires a multiple of {expected} arguments",
+                self.name()
+            );
+        } else if !is_packed && len != expected {
+            bail!("{} requires {expected} arguments", self.name());
+        }
+
+        Ok(())
+    }
+}
+
+/// Built-in types that are written as a constructor with integer arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IntegerType {
+    Vector3i,
+    Vector4i,
+    Rect2i,
+    Rid,
+    PackedByteArray,
+    PackedInt32Array,
+    PackedInt64Array,
+}
+
+impl IntegerType {
+    const ALL: [Self; 7] = [
+        Self::Vector3i,
+        Self::Vector4i,
+        Self::Rect2i,
+        Self::Rid,
+        Self::PackedByteArray,
+        Self::PackedInt32Array,
+        Self::PackedInt64Array,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Vector3i => "Vector3i",
+            Self::Vector4i => "Vector4i",
+            Self::Rect2i => "Rect2i",
+            Self::Rid => "RID",
+            Self::PackedByteArray => "PackedByteArray",
+            Self::PackedInt32Array => "PackedInt32Array",
+            Self::PackedInt64Array => "PackedInt64Array",
+        }
+    }
+
+    fn check_len(self, len: usize) -> Result<()> {
+        let valid = match self {
+            Self::Vector3i => len == 3,
+            Self::Vector4i | Self::Rect2i => len == 4,
+            Self::Rid => len <= 1,
+            Self::PackedByteArray | Self::PackedInt32Array | Self::PackedInt64Array => true,
+        };
+
+        if !valid {
+            bail!("unexpected number of arguments to {}", self.name());
+        }
+
+        Ok(())
+    }
+}
+
+/// The element type of a typed array or dictionary.
+#[derive(Debug, Clone)]
+pub(crate) enum ElementType {
+    /// A built-in type or class name, such as `int` or `Texture2D`.
+    Named(String),
+    /// A script class.
+    ExtResource(String),
+}
+
+impl ElementType {
+    fn parse(tokens: &mut Tokenizer) -> Result<Self> {
+        match tokens.next_token()? {
+            Some(Token::Identifier(name)) if name == "ExtResource" => Ok(Self::ExtResource(
+                Value::parse_string_constructor(tokens, "ExtResource")?,
+            )),
+            Some(Token::Identifier(name)) => Ok(Self::Named(name)),
+            Some(token) => bail!("expected a type name, but found {token:?}"),
+            None => bail!("expected a type name"),
+        }
+    }
+}
+
+impl GodotFmt for ElementType {
+    fn godot_fmt(&self, w: &mut dyn Write) -> io::Result<()> {
+        match self {
+            ElementType::Named(name) => write!(w, "{name}"),
+            ElementType::ExtResource(id) => write!(w, r#"ExtResource("{id}")"#),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Vector2i {
     pub x: i64,
     pub y: i64,
 }
@@ -413,7 +941,7 @@ impl From<[u32; 2]> for Vector2i {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum Color {
     Rgba(f64, f64, f64, f64),
     Html(String),
@@ -438,7 +966,7 @@ impl GodotFmt for Color {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum Token {
     CurlyBracketOpen,
     CurlyBracketClose,
@@ -449,6 +977,7 @@ enum Token {
     Identifier(String),
     String(String),
     StringName(String),
+    NodePath(String),
     Integer(i64),
     Double(f64),
     Color(String),
@@ -459,18 +988,32 @@ enum Token {
 }
 
 struct Tokenizer {
-    bytes: Bytes<BufReader<File>>,
+    bytes: Bytes<BufReader<Box<dyn Read>>>,
     saved: Option<u8>,
+    saved_token: Option<Token>,
 }
 
 impl Tokenizer {
+    fn save_token(&mut self, token: Token) {
+        assert!(self.saved_token.is_none());
+        self.saved_token = Some(token);
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next_token()? {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => bail!("expected {expected:?}, but found {token:?}"),
+            None => bail!("expected {expected:?}"),
+        }
+    }
+
     fn next_byte(&mut self) -> Result<Option<u8>> {
         if let Some(c) = self.saved.take() {
             Ok(Some(c))
         } else if let Some(c) = self.bytes.next() {
             Ok(Some(c?))
         } else {
-            return Ok(None);
+            Ok(None)
         }
     }
 
@@ -480,6 +1023,10 @@ impl Tokenizer {
     }
 
     fn next_token(&mut self) -> Result<Option<Token>> {
+        if let Some(token) = self.saved_token.take() {
+            return Ok(Some(token));
+        }
+
         loop {
             let Some(character) = self.next_byte()? else {
                 return Ok(None);
@@ -519,35 +1066,37 @@ impl Tokenizer {
 
                     return Ok(Some(Token::Color(color_str)));
                 }
-                b'"' | b'@' | b'&' => {
-                    // StringName
-                    let is_string_name = if matches!(character, b'@' | b'&') {
-                        if self.next_byte()? != Some(b'"') {
-                            bail!("expected '\"' after '&'");
-                        }
-
-                        true
-                    } else {
-                        false
-                    };
-
-                    let mut string = String::new();
+                b'"' | b'@' | b'&' | b'^' => {
+                    // StringName or NodePath
+                    if character != b'"' && self.next_byte()? != Some(b'"') {
+                        bail!("expected '\"' after '{}'", character as char);
+                    }
 
-                    // Preserves escape sequences. Change it if we want to parse the content.
+                    // Preserves escape sequences and line breaks. Change it if we
+                    // want to parse the content.
+                    let mut bytes = Vec::new();
                     loop {
                         match self.next_byte()? {
                             None => bail!("unterminated string"),
                             Some(b'"') => break,
-                            Some(b'\n') => {}
-                            Some(c) => string.push(c as char),
+                            Some(b'\\') => {
+                                bytes.push(b'\\');
+                                match self.next_byte()? {
+                                    None => bail!("unterminated string"),
+                                    Some(c) => bytes.push(c),
+                                }
+                            }
+                            Some(c) => bytes.push(c),
                         }
                     }
 
-                    if is_string_name {
-                        return Ok(Some(Token::StringName(string)));
-                    } else {
-                        return Ok(Some(Token::String(string)));
-                    }
+                    let string = String::from_utf8(bytes).context("expected a UTF-8 string")?;
+
+                    return Ok(Some(match character {
+                        b'@' | b'&' => Token::StringName(string),
+                        b'^' => Token::NodePath(string),
+                        _ => Token::String(string),
+                    }));
                 }
                 b'-' | b'0'..=b'9' => {
                     #[derive(Clone, Copy, PartialEq, Eq)]
@@ -569,6 +1118,16 @@ impl Tokenizer {
                         Some(character)
                     };
 
+                    // Negative infinity may be written as `-inf`.
+                    if let Some(c) = next.filter(u8::is_ascii_alphabetic) {
+                        self.save_byte(c);
+                        let Some(Token::Identifier(id)) = self.next_token()? else {
+                            unreachable!("a letter should start an identifier");
+                        };
+
+                        return Ok(Some(Token::Identifier(format!("-{id}"))));
+                    }
+
                     let mut is_float = false;
                     let mut exp_begin = false;
                     let mut exp_sign = false;
@@ -679,7 +1238,7 @@ impl GodotFmt for i64 {
         let mut string = vec![b' '; chars];
         n = *self;
         loop {
-            let modulus = (n % BASE).abs() as u8;
+            let modulus = (n % BASE).unsigned_abs() as u8;
 
             chars -= 1;
             if modulus >= 10 {
@@ -716,20 +1275,28 @@ impl GodotFmt for f64 {
             if *self > 0.0 {
                 w.write_all(b"inf")
             } else {
-                w.write_all(b"neg_inf")
+                w.write_all(b"inf_neg")
             }
         } else {
             // Godot uses `s[n]printf` and a bunch of compiler specific settings
             // to get the C99 output format. `ryu` uses scientific notation for
             // long numbers, at least, but adds ".0" to integers. Trimming away
-            // the ".0" will have to do for now.
+            // the ".0" will have to do for now. The exponent is written with a
+            // sign and at least two digits, as in C99.
             let mut buffer = ryu::Buffer::new();
-            w.write_all(
-                buffer
-                    .format_finite(*self)
-                    .trim_end_matches(".0")
-                    .as_bytes(),
-            )
+            let number = buffer.format_finite(*self);
+
+            match number.split_once('e') {
+                Some((mantissa, exponent)) => {
+                    let (sign, digits) = match exponent.strip_prefix('-') {
+                        Some(digits) => ('-', digits),
+                        None => ('+', exponent),
+                    };
+                    let mantissa = mantissa.trim_end_matches(".0");
+                    write!(w, "{mantissa}e{sign}{digits:0>2}")
+                }
+                None => w.write_all(number.trim_end_matches(".0").as_bytes()),
+            }
         }
     }
 }
@@ -745,9 +1312,159 @@ impl<W: Write> GodotWriter<W> {
     }
 
     pub(crate) fn write_tag(&mut self, tag: &Tag) -> Result<()> {
-        writeln!(self.writer, "")?;
+        writeln!(self.writer)?;
         tag.godot_fmt(&mut self.writer)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{parse, GodotFile, GodotWriter, Value};
+
+    fn parse_text(text: &str) -> GodotFile {
+        parse(Cursor::new(text.as_bytes().to_vec())).expect("the text should parse")
+    }
+
+    fn round_trip(text: &str) -> String {
+        let file = parse_text(text);
+
+        let mut output = Vec::new();
+        let mut writer =
+            GodotWriter::begin(&mut output, &file.header).expect("the header should be written");
+        for tag in &file.tags {
+            writer.write_tag(tag).expect("the tag should be written");
+        }
+
+        String::from_utf8(output).expect("the output should be UTF-8")
+    }
+
+    fn assert_round_trip(text: &str) {
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn resource_file() {
+        assert_round_trip(
+            r#"[gd_resource type="TileSet" load_steps=3 format=3 uid="uid://b1x4y5"]
+
+[ext_resource type="Texture2D" uid="uid://c2a3" path="res://tiles.png" id="1_abc"]
+
+[sub_resource type="TileSetAtlasSource" id="TileSetAtlasSource_1"]
+texture = ExtResource("1_abc")
+texture_region_size = Vector2i(16, 16)
+0:0/0 = 0
+0:0/0/terrain_set = 0
+0:0/0/terrains_peering_bit/right_side = -1
+
+[resource]
+tile_size = Vector2i(16, 16)
+terrain_set_0/terrain_0/color = Color(0.5, 0.25, 1, 1)
+sources/0 = SubResource("TileSetAtlasSource_1")
+"#,
+        );
+    }
+
+    #[test]
+    fn scalars() {
+        assert_round_trip(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+null_value = null
+bools = [true, false]
+integers = [0, -12, 9007199254740993]
+floats = [1.0, -0.5, 0.125, 1e+20, 2.5e-07]
+special_floats = [inf, inf_neg, nan]
+strings = ["text \"quoted\"", &"name", NodePath("Path/To:property")]
+html_color = #ff8000
+"#,
+        );
+    }
+
+    #[test]
+    fn integers_and_floats_stay_distinct() {
+        let file = parse_text(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+integer = 1
+float = 1.0
+"#,
+        );
+
+        let assigns = &file.tags[0].assigns;
+        assert!(matches!(assigns[0].value, Value::Integer(1)));
+        assert!(matches!(assigns[1].value, Value::Double(value) if value == 1.0));
+    }
+
+    #[test]
+    fn built_in_types() {
+        assert_round_trip(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+vector2 = Vector2(1.5, -2)
+vector3i = Vector3i(1, 2, 3)
+rect2i = Rect2i(0, 0, 16, 16)
+transform = Transform2D(1, 0, 0, 1, 8, -8)
+rid = RID()
+callable = Callable()
+signal = Signal()
+"#,
+        );
+    }
+
+    #[test]
+    fn packed_arrays() {
+        assert_round_trip(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+bytes = PackedByteArray("AAEC")
+integers = PackedInt32Array(1, 2, 3)
+floats = PackedFloat32Array(0.5, 1)
+points = PackedVector2Array(0, 0, 16, 0, 16, 16)
+colors = PackedColorArray(1, 0, 0, 1)
+strings = PackedStringArray("a", "b")
+empty = PackedVector2Array()
+"#,
+        );
+    }
+
+    #[test]
+    fn typed_collections() {
+        assert_round_trip(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+array = [1, "two", [3.0]]
+typed_array = Array[int]([1, 2])
+script_array = Array[ExtResource("2_script")]([])
+dictionary = {
+"key": 1,
+2: [Vector2i(1, 2)]
+}
+empty_dictionary = {}
+typed_dictionary = Dictionary[StringName, float]({
+&"speed": 1.5
+})
+"#,
+        );
+    }
+
+    #[test]
+    fn objects() {
+        assert_round_trip(
+            r#"[gd_resource type="Resource" format=3]
+
+[resource]
+object = Object(RefCounted,"script":null,"value":Vector2(1, 2))
+resources = [SubResource("Sub_1"), ExtResource("1_abc"), Resource("res://other.tres")]
+"#,
+        );
+    }
+}
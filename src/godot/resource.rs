@@ -1,76 +1,89 @@
-use std::{fs::File, io::BufWriter, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-use crate::config::Config;
+use crate::config::{
+    Config, CustomDataLayerConfig, CustomDataType, TerrainMode, TileLayout, TileOffsetAxis,
+    TileShape,
+};
 
-use super::godot_file::{Color, Field, GodotFile, GodotWriter, Tag, TagAssign, Value, Vector2i};
+use super::godot_file::{
+    Color, Field, FloatType, GodotFile, GodotWriter, IntegerType, Tag, TagAssign, Value, Vector2i,
+};
+
+/// The order Godot writes the `[resource]` properties of a tile set in.
+/// Entries ending with `_` or `/` are prefixes.
+const RESOURCE_PROPERTY_ORDER: &[&str] = &[
+    "tile_shape",
+    "tile_layout",
+    "tile_offset_axis",
+    "tile_size",
+    "uv_clipping",
+    "occlusion_layer_",
+    "physics_layer_",
+    "terrain_set_",
+    "navigation_layer_",
+    "custom_data_layer_",
+    "sources/",
+    "tile_proxies/",
+    "pattern_",
+];
+
+/// The order Godot writes the properties of an atlas source in, excluding the
+/// tiles, which are written last.
+const ATLAS_SOURCE_PROPERTY_ORDER: &[&str] = &[
+    "texture",
+    "margins",
+    "separation",
+    "texture_region_size",
+    "use_texture_padding",
+];
 
 #[derive(Debug)]
 pub struct TileSetResource {
-    uid: String,
+    header: Tag,
+    /// The atlas sources that tilecutter manages. The first one is the first
+    /// atlas source in the file, and the others have been added for atlas
+    /// images that didn't fit in one texture.
+    pub atlas_sources: Vec<AtlasSource>,
+    /// All tags before `[resource]` in their original order.
+    tags: Vec<ResourceTag>,
+    /// The `[resource]` tag, without the properties tilecutter manages.
+    resource: Tag,
+    /// Unknown tags after `[resource]`, which are kept after it.
+    trailing_tags: Vec<Tag>,
+    /// Terrain colors from the file, so colors picked in Godot are kept.
+    terrain_colors: HashMap<(usize, usize), Value>,
+}
+
+/// An atlas source and its texture.
+#[derive(Debug)]
+pub struct AtlasSource {
+    /// The index of the atlas source in `sources/N`.
+    pub source_id: i64,
     pub texture_resource: TextureResource,
     pub tile_set_atlas_source: TileSetAtlasSource,
 }
 
-impl TileSetResource {
-    pub(crate) fn init_from_file(file: GodotFile) -> Result<Self> {
-        if file.header.name != "gd_resource" {
-            bail!("expected a resource file, but found '{}'", file.header.name);
-        };
-
-        let Some(Field {
-            value: Value::String(uid),
-            ..
-        }) = file
-            .header
-            .fields
-            .into_iter()
-            .find(|f| f.identifier == "uid")
-        else {
-            bail!("expected a uid string on 'gd_resource'");
-        };
-
-        let mut texture_resource = None;
-        let mut tile_set_atlas_source = None;
-
-        for tag in file.tags {
-            match &*tag.name {
-                "ext_resource" => {
-                    if texture_resource.is_none() {
-                        texture_resource = Some(TextureResource::init_from_tag(tag)?)
-                    } else {
-                        bail!("expected only one 'ext_resource'");
-                    }
-                }
-                "sub_resource" => {
-                    if tile_set_atlas_source.is_none() {
-                        tile_set_atlas_source = Some(TileSetAtlasSource::init_from_tag(tag)?)
-                    } else {
-                        bail!("expected only one 'sub_resource'");
-                    }
-                }
-                "resource" => {}
-                other => bail!("unexpected tag '{other}'"),
-            }
-        }
-
-        let Some(texture_resource) = texture_resource else {
-            bail!("missing external 'Texture2D' resource");
-        };
-
-        let Some(tile_set_atlas_source) = tile_set_atlas_source else {
-            bail!("missing 'TileSetAtlasSource' resource");
-        };
-
-        Ok(TileSetResource {
-            uid,
-            texture_resource,
-            tile_set_atlas_source,
-        })
-    }
+/// A resource tag, where the texture and atlas source tags refer to
+/// `atlas_sources` by index. Other tags, including unknown ones, are kept as
+/// they are.
+#[derive(Debug)]
+enum ResourceTag {
+    Texture(usize),
+    AtlasSource(usize),
+    Other(Tag),
+}
 
-    pub(crate) fn print_to_file(&self, path: impl AsRef<Path>, config: &Config) -> Result<()> {
+impl TileSetResource {
+    /// Creates a new tile set resource with a single atlas source, using the
+    /// texture at `texture_path`.
+    pub fn new(uid: String, texture_path: String, texture_uid: String) -> Self {
         let header = Tag {
             name: "gd_resource".into(),
             fields: vec![
@@ -80,7 +93,7 @@ impl TileSetResource {
                 },
                 Field {
                     identifier: "load_steps".into(),
-                    value: Value::Integer(3), // self + resources
+                    value: Value::Integer(3),
                 },
                 Field {
                     identifier: "format".into(),
@@ -88,121 +101,835 @@ impl TileSetResource {
                 },
                 Field {
                     identifier: "uid".into(),
-                    value: Value::String(self.uid.clone()),
+                    value: Value::String(uid),
                 },
             ],
             assigns: Vec::new(),
         };
 
-        let image_tag = Tag {
-            name: "ext_resource".into(),
-            fields: vec![
-                Field {
-                    identifier: "type".into(),
-                    value: Value::String("Texture2D".into()),
-                },
-                Field {
-                    identifier: "uid".into(),
-                    value: Value::String(self.texture_resource.uid.clone()),
-                },
-                Field {
-                    identifier: "path".into(),
-                    value: Value::String(self.texture_resource.path.clone()),
-                },
-                Field {
-                    identifier: "id".into(),
-                    value: Value::String(self.texture_resource.id.clone()),
-                },
-            ],
+        let texture_resource = TextureResource {
+            uid: texture_uid,
+            path: texture_path,
+            id: "1_texture".into(),
+            fields: Vec::new(),
+        };
+
+        let tile_set_atlas_source = TileSetAtlasSource {
+            id: "TileSetAtlasSource_1".into(),
+            texture: texture_resource.id.clone(),
+            margins: Vector2i::default(),
+            separation: Vector2i::default(),
+            texture_region_size: Vector2i { x: 16, y: 16 },
+            tiles: Vec::new(),
+            fields: Vec::new(),
             assigns: Vec::new(),
         };
 
-        let mut atlas_tag = Tag {
-            name: "sub_resource".into(),
-            fields: vec![
-                Field {
-                    identifier: "type".into(),
-                    value: Value::String("TileSetAtlasSource".into()),
-                },
-                Field {
-                    identifier: "id".into(),
-                    value: Value::String(self.tile_set_atlas_source.id.clone()),
-                },
-            ],
-            assigns: vec![
-                TagAssign {
-                    assign: "texture".into(),
-                    value: Value::ExtResource(self.tile_set_atlas_source.texture.clone()),
-                },
-                TagAssign {
-                    assign: "texture_region_size".into(),
-                    value: Value::Vector2i(self.tile_set_atlas_source.texture_region_size),
-                },
-            ],
+        TileSetResource {
+            header,
+            atlas_sources: vec![AtlasSource {
+                source_id: 0,
+                texture_resource,
+                tile_set_atlas_source,
+            }],
+            tags: vec![ResourceTag::Texture(0), ResourceTag::AtlasSource(0)],
+            resource: Tag {
+                name: "resource".into(),
+                fields: Vec::new(),
+                assigns: Vec::new(),
+            },
+            trailing_tags: Vec::new(),
+            terrain_colors: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn init_from_file(file: GodotFile) -> Result<Self> {
+        if file.header.name != "gd_resource" {
+            bail!("expected a resource file, but found '{}'", file.header.name);
         };
 
-        for tile in &self.tile_set_atlas_source.tiles {
-            tile.append_assigns(&mut atlas_tag.assigns);
+        let mut tags = Vec::new();
+        let mut resource = None;
+        let mut trailing_tags = Vec::new();
+
+        for tag in file.tags {
+            match &*tag.name {
+                "ext_resource" | "sub_resource" => tags.push(tag),
+                "resource" => {
+                    if resource.is_none() {
+                        resource = Some(tag);
+                    } else {
+                        bail!("expected only one 'resource'");
+                    }
+                }
+                _ if resource.is_some() => trailing_tags.push(tag),
+                _ => tags.push(tag),
+            }
         }
 
-        let mut resource_tag = Tag {
+        let mut resource = resource.unwrap_or_else(|| Tag {
             name: "resource".into(),
             fields: Vec::new(),
-            assigns: vec![
-                TagAssign {
-                    assign: "tile_shape".into(),
-                    value: Value::Integer(3), // Hexagon
-                },
-                TagAssign {
-                    assign: "tile_offset_axis".into(),
-                    value: Value::Integer(1),
-                },
-                TagAssign {
-                    assign: "tile_size".into(),
-                    value: Value::Vector2i(self.tile_set_atlas_source.texture_region_size),
-                },
-            ],
+            assigns: Vec::new(),
+        });
+
+        let Some(atlas_index) = tags.iter().position(|tag| {
+            tag.name == "sub_resource" && tag.string_field("type") == Some("TileSetAtlasSource")
+        }) else {
+            bail!("missing 'TileSetAtlasSource' resource");
+        };
+
+        let Some(texture_id) = tags[atlas_index]
+            .assigns
+            .iter()
+            .find(|assign| assign.assign == "texture")
+            .and_then(|assign| match &assign.value {
+                Value::ExtResource(id) => Some(id.clone()),
+                _ => None,
+            })
+        else {
+            bail!("missing tile atlas source 'texture'");
+        };
+
+        let Some(texture_index) = tags.iter().position(|tag| {
+            tag.name == "ext_resource" && tag.string_field("id") == Some(&*texture_id)
+        }) else {
+            bail!("missing external 'Texture2D' resource");
+        };
+
+        // Additional atlas sources use textures next to the first one, and are
+        // only managed as long as they follow each other.
+        let mut source_indices = vec![(texture_index, atlas_index)];
+        let first_texture_path = tags[texture_index].string_field("path").unwrap_or_default();
+
+        for page in 1.. {
+            let texture_path = page_texture_path(first_texture_path, page);
+            let page_indices = tags
+                .iter()
+                .position(|tag| {
+                    tag.name == "ext_resource" && tag.string_field("path") == Some(&*texture_path)
+                })
+                .and_then(|texture_index| {
+                    let texture_id = tags[texture_index].string_field("id")?;
+                    let atlas_index = tags.iter().position(|tag| {
+                        tag.name == "sub_resource"
+                            && tag.string_field("type") == Some("TileSetAtlasSource")
+                            && tag.assigns.iter().any(|assign| {
+                                assign.assign == "texture"
+                                    && matches!(&assign.value, Value::ExtResource(id) if id == texture_id)
+                            })
+                    })?;
+
+                    Some((texture_index, atlas_index))
+                });
+
+            match page_indices {
+                Some(indices) => source_indices.push(indices),
+                None => break,
+            }
+        }
+
+        let mut atlas_sources = Vec::new();
+        let mut source_ids = Vec::new();
+        for &(texture_index, atlas_index) in &source_indices {
+            let tile_set_atlas_source =
+                TileSetAtlasSource::init_from_tag(std::mem::take(&mut tags[atlas_index]))?;
+            let texture_resource =
+                TextureResource::init_from_tag(std::mem::take(&mut tags[texture_index]))?;
+
+            source_ids.push(
+                resource
+                    .assigns
+                    .iter()
+                    .find_map(|assign| match &assign.value {
+                        Value::SubResource(id) if *id == tile_set_atlas_source.id => {
+                            assign.assign.strip_prefix("sources/")?.parse::<i64>().ok()
+                        }
+                        _ => None,
+                    }),
+            );
+
+            atlas_sources.push(AtlasSource {
+                source_id: 0,
+                texture_resource,
+                tile_set_atlas_source,
+            });
+        }
+
+        let tags = tags
+            .into_iter()
+            .enumerate()
+            .map(|(index, tag)| {
+                for (page, &(texture_index, atlas_index)) in source_indices.iter().enumerate() {
+                    if index == atlas_index {
+                        return ResourceTag::AtlasSource(page);
+                    } else if index == texture_index {
+                        return ResourceTag::Texture(page);
+                    }
+                }
+
+                ResourceTag::Other(tag)
+            })
+            .collect();
+
+        let mut terrain_colors = HashMap::new();
+        let source_paths = source_ids
+            .iter()
+            .flatten()
+            .map(|source_id| format!("sources/{source_id}"))
+            .collect::<HashSet<_>>();
+
+        resource.assigns.retain_mut(|assign| match &*assign.assign {
+            "tile_shape" | "tile_layout" | "tile_offset_axis" | "tile_size" => false,
+            path if source_paths.contains(path) => false,
+            path if path.starts_with("terrain_set_") => {
+                if let Some(key) = parse_terrain_color_path(path) {
+                    terrain_colors.insert(key, std::mem::replace(&mut assign.value, Value::Null));
+                }
+                false
+            }
+            _ => true,
+        });
+
+        for (page, source_id) in source_ids.into_iter().enumerate() {
+            atlas_sources[page].source_id = match source_id {
+                Some(source_id) => source_id,
+                None if page == 0 => 0,
+                None => unused_source_id(&resource, &atlas_sources[..page]),
+            };
+        }
+
+        Ok(TileSetResource {
+            header: file.header,
+            atlas_sources,
+            tags,
+            resource,
+            trailing_tags,
+            terrain_colors,
+        })
+    }
+
+    /// Adds or removes atlas sources, so that there are `count` of them, and
+    /// returns the removed ones. The added sources use textures next to the
+    /// first one, with the same margins, separation and tile size.
+    pub fn set_atlas_source_count(&mut self, count: usize) -> Vec<AtlasSource> {
+        let count = count.max(1);
+        let removed = if count < self.atlas_sources.len() {
+            self.atlas_sources.split_off(count)
+        } else {
+            Vec::new()
+        };
+
+        self.tags.retain(|tag| match *tag {
+            ResourceTag::Texture(page) | ResourceTag::AtlasSource(page) => page < count,
+            ResourceTag::Other(_) => true,
+        });
+
+        while self.atlas_sources.len() < count {
+            self.add_atlas_source();
+        }
+
+        removed
+    }
+
+    /// The tiles of each atlas source.
+    pub fn layouts(&self) -> Vec<&[Tile]> {
+        self.atlas_sources
+            .iter()
+            .map(|source| &*source.tile_set_atlas_source.tiles)
+            .collect()
+    }
+
+    pub fn print_to_file(&self, path: impl AsRef<Path>, config: &Config) -> Result<()> {
+        let file = File::create(path)?;
+        self.print(BufWriter::new(file), config)
+    }
+
+    pub fn print(&self, writer: impl Write, config: &Config) -> Result<()> {
+        let tile_shape = match config.tile_set.tile_shape {
+            TileShape::Square => 0,
+            TileShape::Isometric => 1,
+            TileShape::HalfOffsetSquare => 2,
+            TileShape::Hexagon => 3,
+        };
+
+        let tile_layout = match config.tile_set.tile_layout {
+            TileLayout::Stacked => 0,
+            TileLayout::StackedOffset => 1,
+            TileLayout::StairsRight => 2,
+            TileLayout::StairsDown => 3,
+            TileLayout::DiamondRight => 4,
+            TileLayout::DiamondDown => 5,
+        };
+
+        let tile_offset_axis = match config.tile_set.tile_offset_axis {
+            TileOffsetAxis::Horizontal => 0,
+            TileOffsetAxis::Vertical => 1,
         };
 
+        let mut resource_assigns = vec![
+            TagAssign {
+                assign: "tile_shape".into(),
+                value: Value::Integer(tile_shape),
+            },
+            TagAssign {
+                assign: "tile_layout".into(),
+                value: Value::Integer(tile_layout),
+            },
+            TagAssign {
+                assign: "tile_offset_axis".into(),
+                value: Value::Integer(tile_offset_axis),
+            },
+            TagAssign {
+                assign: "tile_size".into(),
+                value: Value::Vector2i(
+                    self.atlas_sources[0]
+                        .tile_set_atlas_source
+                        .texture_region_size,
+                ),
+            },
+        ];
+
+        for (layer_index, layer) in config.occlusion_layers.iter().enumerate() {
+            resource_assigns.push(TagAssign {
+                assign: format!("occlusion_layer_{layer_index}/light_mask"),
+                value: Value::Integer(layer.light_mask.into()),
+            });
+            resource_assigns.push(TagAssign {
+                assign: format!("occlusion_layer_{layer_index}/sdf_collision"),
+                value: Value::Bool(layer.sdf_collision),
+            });
+        }
+
+        for (layer_index, layer) in config.physics_layers.iter().enumerate() {
+            resource_assigns.push(TagAssign {
+                assign: format!("physics_layer_{layer_index}/collision_layer"),
+                value: Value::Integer(layer.collision_layer.into()),
+            });
+            resource_assigns.push(TagAssign {
+                assign: format!("physics_layer_{layer_index}/collision_mask"),
+                value: Value::Integer(layer.collision_mask.into()),
+            });
+        }
+
+        for (layer_index, layer) in config.navigation_layers.iter().enumerate() {
+            resource_assigns.push(TagAssign {
+                assign: format!("navigation_layer_{layer_index}/layers"),
+                value: Value::Integer(layer.layers.into()),
+            });
+        }
+
+        for (layer_index, layer) in config.custom_data_layers.iter().enumerate() {
+            let ty = match layer.ty {
+                CustomDataType::Bool => 1,
+                CustomDataType::Int => 2,
+                CustomDataType::Float => 3,
+                CustomDataType::String => 4,
+                CustomDataType::Vector2 => 5,
+                CustomDataType::Vector2i => 6,
+                CustomDataType::Color => 20,
+                CustomDataType::StringName => 21,
+            };
+
+            resource_assigns.push(TagAssign {
+                assign: format!("custom_data_layer_{layer_index}/name"),
+                value: Value::String(layer.name.clone()),
+            });
+            resource_assigns.push(TagAssign {
+                assign: format!("custom_data_layer_{layer_index}/type"),
+                value: Value::Integer(ty),
+            });
+        }
+
         for (set_index, terrain_set) in config.terrain_sets.iter().enumerate() {
-            resource_tag.assigns.push(TagAssign {
+            let mode = match terrain_set.mode {
+                TerrainMode::CornersAndSides => 0,
+                TerrainMode::Corners => 1,
+                TerrainMode::Sides => 2,
+            };
+
+            resource_assigns.push(TagAssign {
                 assign: format!("terrain_set_{set_index}/mode"),
-                value: Value::Integer(2),
+                value: Value::Integer(mode),
             });
 
             for (terrain_index, terrain) in terrain_set.terrains.iter().enumerate() {
-                resource_tag.assigns.push(TagAssign {
+                resource_assigns.push(TagAssign {
                     assign: format!("terrain_set_{set_index}/terrain_{terrain_index}/name"),
                     value: Value::String(terrain.name.clone()),
                 });
 
-                resource_tag.assigns.push(TagAssign {
+                let color = self
+                    .terrain_colors
+                    .get(&(set_index, terrain_index))
+                    .cloned()
+                    .unwrap_or(Value::Color(Color::Rgba(0.0, 0.0, 0.0, 1.0)));
+
+                resource_assigns.push(TagAssign {
                     assign: format!("terrain_set_{set_index}/terrain_{terrain_index}/color"),
-                    value: Value::Color(Color::Rgba(0.0, 0.0, 0.0, 1.0)),
+                    value: color,
                 });
             }
         }
 
-        resource_tag.assigns.push(TagAssign {
-            assign: "sources/0".into(),
-            value: Value::SubResource(self.tile_set_atlas_source.id.clone()),
-        });
+        for source in &self.atlas_sources {
+            resource_assigns.push(TagAssign {
+                assign: format!("sources/{}", source.source_id),
+                value: Value::SubResource(source.tile_set_atlas_source.id.clone()),
+            });
+        }
+
+        let managed_prefixes = managed_layer_prefixes(config);
+        let kept_assigns = self
+            .resource
+            .assigns
+            .iter()
+            .filter(|assign| {
+                !managed_prefixes
+                    .iter()
+                    .any(|prefix| assign.assign.starts_with(prefix))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let resource_tag = Tag {
+            name: self.resource.name.clone(),
+            fields: self.resource.fields.clone(),
+            assigns: merge_assigns(&kept_assigns, resource_assigns, RESOURCE_PROPERTY_ORDER),
+        };
+
+        let (material_tags, material_ids) = self.material_resources();
+        let atlas_source_tags = self
+            .atlas_sources
+            .iter()
+            .enumerate()
+            .map(|(page, source)| {
+                source
+                    .tile_set_atlas_source
+                    .to_tag(config, &material_ids, page)
+            })
+            .collect::<Vec<_>>();
+        let generated_tags = self
+            .atlas_sources
+            .iter()
+            .enumerate()
+            .map(|(page, source)| {
+                source
+                    .tile_set_atlas_source
+                    .tiles
+                    .iter()
+                    .flat_map(|tile| tile.sub_resource_tags(page))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let generated_ids = generated_tags
+            .iter()
+            .flatten()
+            .filter_map(|tag| tag.string_field("id"))
+            .collect::<HashSet<_>>();
+
+        let mut tags = Vec::new();
+        for tag in &self.tags {
+            match tag {
+                &ResourceTag::Texture(page) => {
+                    tags.push(self.atlas_sources[page].texture_resource.to_tag());
+
+                    if page == 0 {
+                        tags.extend(material_tags.iter().cloned());
+                    }
+                }
+                &ResourceTag::AtlasSource(page) => {
+                    // Sub-resources have to come before they are used.
+                    tags.extend(generated_tags[page].iter().cloned());
+                    tags.push(atlas_source_tags[page].clone());
+                }
+                ResourceTag::Other(tag) => {
+                    // Sub-resources from the previous export are replaced by
+                    // the newly generated ones.
+                    let is_replaced = tag
+                        .string_field("id")
+                        .is_some_and(|id| generated_ids.contains(id));
+
+                    if !is_replaced {
+                        tags.push(tag.clone());
+                    }
+                }
+            }
+        }
+
+        remove_unused_generated_tags(&mut tags, &resource_tag, &self.trailing_tags);
+
+        let mut header = self.header.clone();
+        if let Some(load_steps) = header
+            .fields
+            .iter_mut()
+            .find(|field| field.identifier == "load_steps")
+        {
+            let resource_count = tags
+                .iter()
+                .filter(|tag| matches!(&*tag.name, "ext_resource" | "sub_resource"))
+                .count();
+            load_steps.value = Value::Integer(resource_count as i64 + 1); // self + resources
+        }
+
+        let mut writer = GodotWriter::begin(writer, &header)?;
+
+        for tag in &tags {
+            writer.write_tag(tag)?;
+        }
 
-        let file = File::create(path)?;
-        let mut writer = GodotWriter::begin(BufWriter::new(file), &header)?;
-        writer.write_tag(&image_tag)?;
-        writer.write_tag(&atlas_tag)?;
         writer.write_tag(&resource_tag)?;
 
+        for tag in &self.trailing_tags {
+            writer.write_tag(tag)?;
+        }
+
         Ok(())
     }
+
+    /// Finds or makes external resources for the tiles' materials. Returns the
+    /// new resource tags and the IDs of all materials, by path.
+    fn material_resources(&self) -> (Vec<Tag>, HashMap<&str, String>) {
+        let existing_tags = self.tags.iter().filter_map(|tag| match tag {
+            ResourceTag::Other(tag) if tag.name == "ext_resource" => Some(tag),
+            _ => None,
+        });
+        let mut used_ids = existing_tags
+            .clone()
+            .filter_map(|tag| tag.string_field("id"))
+            .chain(
+                self.atlas_sources
+                    .iter()
+                    .map(|source| &*source.texture_resource.id),
+            )
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+
+        let mut new_tags = Vec::new();
+        let mut material_ids = HashMap::new();
+
+        let tiles = self
+            .atlas_sources
+            .iter()
+            .flat_map(|source| &source.tile_set_atlas_source.tiles);
+
+        for tile in tiles {
+            let Some(path) = tile.rendering.material.as_deref() else {
+                continue;
+            };
+
+            if material_ids.contains_key(path) {
+                continue;
+            }
+
+            let existing_id = existing_tags
+                .clone()
+                .find(|tag| tag.string_field("path") == Some(path))
+                .and_then(|tag| tag.string_field("id"));
+
+            let id = match existing_id {
+                Some(id) => id.to_owned(),
+                None => {
+                    let id = (2..)
+                        .map(|index| format!("{index}_material"))
+                        .find(|id| !used_ids.contains(id))
+                        .expect("there should be an unused ID");
+
+                    // Godot falls back to the path when there's no UID.
+                    new_tags.push(Tag {
+                        name: "ext_resource".into(),
+                        fields: vec![
+                            Field {
+                                identifier: "type".into(),
+                                value: Value::String("ShaderMaterial".into()),
+                            },
+                            Field {
+                                identifier: "path".into(),
+                                value: Value::String(path.into()),
+                            },
+                            Field {
+                                identifier: "id".into(),
+                                value: Value::String(id.clone()),
+                            },
+                        ],
+                        assigns: Vec::new(),
+                    });
+                    id
+                }
+            };
+
+            used_ids.insert(id.clone());
+            material_ids.insert(path, id);
+        }
+
+        (new_tags, material_ids)
+    }
+
+    fn add_atlas_source(&mut self) {
+        let page = self.atlas_sources.len();
+        let first_source = &self.atlas_sources[0];
+
+        let mut ext_resource_ids = HashSet::new();
+        let mut sub_resource_ids = HashSet::new();
+        for tag in &self.tags {
+            if let ResourceTag::Other(tag) = tag {
+                let ids = match &*tag.name {
+                    "ext_resource" => &mut ext_resource_ids,
+                    "sub_resource" => &mut sub_resource_ids,
+                    _ => continue,
+                };
+                ids.extend(tag.string_field("id").map(str::to_owned));
+            }
+        }
+        for source in &self.atlas_sources {
+            ext_resource_ids.insert(source.texture_resource.id.clone());
+            sub_resource_ids.insert(source.tile_set_atlas_source.id.clone());
+        }
+
+        // Godot falls back to the path when there's no UID.
+        let texture_resource = TextureResource {
+            uid: String::new(),
+            path: page_texture_path(&first_source.texture_resource.path, page),
+            id: (1..)
+                .map(|index| format!("{index}_texture"))
+                .find(|id| !ext_resource_ids.contains(id))
+                .expect("there should be an unused ID"),
+            fields: Vec::new(),
+        };
+
+        let tile_set_atlas_source = TileSetAtlasSource {
+            id: (1..)
+                .map(|index| format!("TileSetAtlasSource_{index}"))
+                .find(|id| !sub_resource_ids.contains(id))
+                .expect("there should be an unused ID"),
+            texture: texture_resource.id.clone(),
+            margins: first_source.tile_set_atlas_source.margins,
+            separation: first_source.tile_set_atlas_source.separation,
+            texture_region_size: first_source.tile_set_atlas_source.texture_region_size,
+            tiles: Vec::new(),
+            fields: Vec::new(),
+            assigns: Vec::new(),
+        };
+
+        // The new tags go after the other tags of the same kind.
+        let insert_after = |tags: &[ResourceTag], is_kind: fn(&ResourceTag) -> bool| {
+            tags.iter()
+                .rposition(is_kind)
+                .map_or(tags.len(), |index| index + 1)
+        };
+        let texture_index = insert_after(&self.tags, |tag| matches!(tag, ResourceTag::Texture(_)));
+        self.tags.insert(texture_index, ResourceTag::Texture(page));
+        let atlas_index =
+            insert_after(&self.tags, |tag| matches!(tag, ResourceTag::AtlasSource(_)));
+        self.tags
+            .insert(atlas_index, ResourceTag::AtlasSource(page));
+
+        let source_id = unused_source_id(&self.resource, &self.atlas_sources);
+        self.atlas_sources.push(AtlasSource {
+            source_id,
+            texture_resource,
+            tile_set_atlas_source,
+        });
+    }
+}
+
+/// The types of the sub-resources that tilecutter generates.
+const GENERATED_RESOURCE_TYPES: &[&str] = &["OccluderPolygon2D", "NavigationPolygon"];
+
+/// The types of the external resources that tilecutter adds.
+const GENERATED_EXT_RESOURCE_TYPES: &[&str] = &["ShaderMaterial"];
+
+/// The property prefixes of the layers that are managed by tilecutter. Layers
+/// are only managed when there are layers of that kind in the config.
+fn managed_layer_prefixes(config: &Config) -> Vec<&'static str> {
+    let mut prefixes = Vec::new();
+
+    if !config.occlusion_layers.is_empty() {
+        prefixes.push("occlusion_layer_");
+    }
+
+    if !config.physics_layers.is_empty() {
+        prefixes.push("physics_layer_");
+    }
+
+    if !config.navigation_layers.is_empty() {
+        prefixes.push("navigation_layer_");
+    }
+
+    if !config.custom_data_layers.is_empty() {
+        prefixes.push("custom_data_");
+    }
+
+    prefixes
+}
+
+/// Removes resources of the generated types that are no longer used.
+fn remove_unused_generated_tags(tags: &mut Vec<Tag>, resource_tag: &Tag, trailing_tags: &[Tag]) {
+    let mut used_sub_resources = HashSet::new();
+    let mut used_ext_resources = HashSet::new();
+    for tag in tags.iter().chain([resource_tag]).chain(trailing_tags) {
+        for field in &tag.fields {
+            field
+                .value
+                .collect_resource_ids(&mut used_sub_resources, &mut used_ext_resources);
+        }
+
+        for assign in &tag.assigns {
+            assign
+                .value
+                .collect_resource_ids(&mut used_sub_resources, &mut used_ext_resources);
+        }
+    }
+
+    tags.retain(|tag| {
+        let (generated_types, used_ids) = match &*tag.name {
+            "sub_resource" => (GENERATED_RESOURCE_TYPES, &used_sub_resources),
+            "ext_resource" => (GENERATED_EXT_RESOURCE_TYPES, &used_ext_resources),
+            _ => return true,
+        };
+
+        let is_generated_type = tag
+            .string_field("type")
+            .is_some_and(|ty| generated_types.contains(&ty));
+
+        !is_generated_type
+            || tag
+                .string_field("id")
+                .is_some_and(|id| used_ids.contains(id))
+    });
+}
+
+/// Merges generated assigns into the ones that were kept from the file. The
+/// result follows Godot's property order, while assigns with unknown names
+/// stay after the assign they followed in the file.
+fn merge_assigns(kept: &[TagAssign], generated: Vec<TagAssign>, order: &[&str]) -> Vec<TagAssign> {
+    let property_order = |name: &str| {
+        order
+            .iter()
+            .position(|&property| {
+                if property.ends_with(['_', '/']) {
+                    name.starts_with(property)
+                } else {
+                    name == property
+                }
+            })
+            .map(|index| index + 1)
+    };
+
+    let mut previous_order = 0;
+    let mut assigns = Vec::new();
+
+    for assign in kept {
+        previous_order = property_order(&assign.assign).unwrap_or(previous_order);
+        assigns.push(((previous_order, true), assign.clone()));
+    }
+
+    for assign in generated {
+        let order = property_order(&assign.assign).unwrap_or(usize::MAX);
+        assigns.push(((order, false), assign));
+    }
+
+    assigns.sort_by_key(|&(order, _)| order);
+    assigns.into_iter().map(|(_, assign)| assign).collect()
+}
+
+/// Reads the tile shape, layout and offset axis from a tile set file, using
+/// Godot's defaults for missing properties.
+pub(crate) fn read_tile_shape(file: &GodotFile) -> Result<(TileShape, TileLayout, TileOffsetAxis)> {
+    let mut tile_shape = TileShape::Square;
+    let mut tile_layout = TileLayout::Stacked;
+    let mut tile_offset_axis = TileOffsetAxis::Horizontal;
+
+    let assigns = file
+        .tags
+        .iter()
+        .filter(|tag| tag.name == "resource")
+        .flat_map(|tag| &tag.assigns);
+
+    for assign in assigns {
+        let value = match (&*assign.assign, &assign.value) {
+            ("tile_shape" | "tile_layout" | "tile_offset_axis", &Value::Integer(value)) => value,
+            ("tile_shape" | "tile_layout" | "tile_offset_axis", _) => {
+                bail!("expected '{}' to be an integer", assign.assign)
+            }
+            _ => continue,
+        };
+
+        match &*assign.assign {
+            "tile_shape" => {
+                tile_shape = match value {
+                    0 => TileShape::Square,
+                    1 => TileShape::Isometric,
+                    2 => TileShape::HalfOffsetSquare,
+                    3 => TileShape::Hexagon,
+                    _ => bail!("unknown tile shape {value}"),
+                }
+            }
+            "tile_layout" => {
+                tile_layout = match value {
+                    0 => TileLayout::Stacked,
+                    1 => TileLayout::StackedOffset,
+                    2 => TileLayout::StairsRight,
+                    3 => TileLayout::StairsDown,
+                    4 => TileLayout::DiamondRight,
+                    5 => TileLayout::DiamondDown,
+                    _ => bail!("unknown tile layout {value}"),
+                }
+            }
+            _ => {
+                tile_offset_axis = match value {
+                    0 => TileOffsetAxis::Horizontal,
+                    1 => TileOffsetAxis::Vertical,
+                    _ => bail!("unknown tile offset axis {value}"),
+                }
+            }
+        }
+    }
+
+    Ok((tile_shape, tile_layout, tile_offset_axis))
+}
+
+/// The texture path of an additional atlas source, which is the first
+/// texture's path with the source's page number added to it.
+fn page_texture_path(first_path: &str, page: usize) -> String {
+    match first_path.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains('/') => {
+            format!("{stem}_{page}.{extension}")
+        }
+        _ => format!("{first_path}_{page}"),
+    }
+}
+
+/// The lowest source ID that isn't used by any of the atlas sources, or by
+/// other sources in the `[resource]` tag.
+fn unused_source_id(resource: &Tag, atlas_sources: &[AtlasSource]) -> i64 {
+    let used_ids = resource
+        .assigns
+        .iter()
+        .filter_map(|assign| assign.assign.strip_prefix("sources/")?.parse().ok())
+        .chain(atlas_sources.iter().map(|source| source.source_id))
+        .collect::<HashSet<i64>>();
+
+    (0..)
+        .find(|id| !used_ids.contains(id))
+        .expect("there should be an unused source ID")
+}
+
+/// Parses `terrain_set_N/terrain_M/color` into `(N, M)`.
+fn parse_terrain_color_path(path: &str) -> Option<(usize, usize)> {
+    let (set, rest) = path.strip_prefix("terrain_set_")?.split_once('/')?;
+    let terrain = rest.strip_prefix("terrain_")?.strip_suffix("/color")?;
+
+    Some((set.parse().ok()?, terrain.parse().ok()?))
 }
 
 #[derive(Debug)]
-pub(crate) struct TextureResource {
+pub struct TextureResource {
     pub uid: String,
     pub path: String,
     pub id: String,
+    /// Fields that tilecutter doesn't manage.
+    fields: Vec<Field>,
 }
 
 impl TextureResource {
@@ -213,6 +940,7 @@ impl TextureResource {
             uid: String::new(),
             path: String::new(),
             id: String::new(),
+            fields: Vec::new(),
         };
 
         for field in tag.fields {
@@ -246,7 +974,7 @@ impl TextureResource {
                     };
                     resource.id = id;
                 }
-                other => bail!("unexpected 'ext_resource' field '{other}'"),
+                _ => resource.fields.push(field),
             }
         }
 
@@ -254,10 +982,6 @@ impl TextureResource {
             bail!("expected texture resource type to be 'Texture2D'");
         }
 
-        if resource.uid.is_empty() {
-            bail!("missing texture resource 'uid'");
-        }
-
         if resource.path.is_empty() {
             bail!("missing texture resource 'path'");
         }
@@ -268,14 +992,51 @@ impl TextureResource {
 
         Ok(resource)
     }
+
+    fn to_tag(&self) -> Tag {
+        let mut fields = vec![Field {
+            identifier: "type".into(),
+            value: Value::String("Texture2D".into()),
+        }];
+
+        // Older files may lack UIDs, in which case Godot falls back to the path.
+        if !self.uid.is_empty() {
+            fields.push(Field {
+                identifier: "uid".into(),
+                value: Value::String(self.uid.clone()),
+            });
+        }
+
+        fields.push(Field {
+            identifier: "path".into(),
+            value: Value::String(self.path.clone()),
+        });
+        fields.push(Field {
+            identifier: "id".into(),
+            value: Value::String(self.id.clone()),
+        });
+        fields.extend(self.fields.iter().cloned());
+
+        Tag {
+            name: "ext_resource".into(),
+            fields,
+            assigns: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug)]
-pub(crate) struct TileSetAtlasSource {
+pub struct TileSetAtlasSource {
     id: String,
     texture: String,
+    pub margins: Vector2i,
+    pub separation: Vector2i,
     pub texture_region_size: Vector2i,
     pub tiles: Vec<Tile>,
+    /// Fields that tilecutter doesn't manage.
+    fields: Vec<Field>,
+    /// Assigns that tilecutter doesn't manage, excluding tile properties.
+    assigns: Vec<TagAssign>,
 }
 
 impl TileSetAtlasSource {
@@ -283,11 +1044,18 @@ impl TileSetAtlasSource {
         let mut found_type = false;
         let mut id = String::new();
         let mut texture = String::new();
+        let mut margins = Vector2i::default();
+        let mut separation = Vector2i::default();
+        let mut texture_region_size = Vector2i { x: 16, y: 16 };
+        let mut tiles = Vec::<Tile>::new();
+        let mut tile_indices = HashMap::new();
+        let mut fields = Vec::new();
+        let mut assigns = Vec::new();
 
         for field in tag.fields {
             match &*field.identifier {
                 "type" => {
-                    let Value::String(ty) = field.value else {
+                    let Value::String(ty) = &field.value else {
                         bail!("expected 'type' to be a string");
                     };
 
@@ -303,7 +1071,7 @@ impl TileSetAtlasSource {
                     };
                     id = value;
                 }
-                other => bail!("unexpected 'sub_resource' field '{other}'"),
+                _ => fields.push(field),
             }
         }
 
@@ -316,7 +1084,66 @@ impl TileSetAtlasSource {
 
                     texture = value;
                 }
-                _ => {}
+                "margins" => {
+                    let Value::Vector2i(value) = assign.value else {
+                        bail!("expected 'margins' to be a 'Vector2i'");
+                    };
+
+                    margins = value;
+                }
+                "separation" => {
+                    let Value::Vector2i(value) = assign.value else {
+                        bail!("expected 'separation' to be a 'Vector2i'");
+                    };
+
+                    separation = value;
+                }
+                "texture_region_size" => {
+                    let Value::Vector2i(value) = assign.value else {
+                        bail!("expected 'texture_region_size' to be a 'Vector2i'");
+                    };
+
+                    texture_region_size = value;
+                }
+                path => {
+                    let Some((position, property)) = parse_tile_path(path) else {
+                        assigns.push(assign);
+                        continue;
+                    };
+
+                    let index = *tile_indices.entry(position).or_insert_with(|| {
+                        tiles.push(Tile {
+                            position,
+                            size_in_atlas: Vector2i { x: 1, y: 1 },
+                            terrain_set: None,
+                            terrain: None,
+                            terrains_peering_bit: Default::default(),
+                            probability: None,
+                            occluder_polygons: Vec::new(),
+                            physics_polygons: Vec::new(),
+                            navigation_polygons: Vec::new(),
+                            custom_data: Vec::new(),
+                            animation: None,
+                            alternatives: Vec::new(),
+                            rendering: Default::default(),
+                            properties: Vec::new(),
+                        });
+                        tiles.len() - 1
+                    });
+
+                    let tile = &mut tiles[index];
+
+                    let is_owned = tile
+                        .set_property(property, &assign.value)
+                        .with_context(|| format!("could not read tile property '{path}'"))?;
+
+                    if !is_owned {
+                        tile.properties.push(TagAssign {
+                            assign: property.into(),
+                            value: assign.value,
+                        });
+                    }
+                }
             }
         }
 
@@ -335,29 +1162,617 @@ impl TileSetAtlasSource {
         Ok(Self {
             id,
             texture,
-            texture_region_size: Vector2i { x: 0, y: 0 },
-            tiles: Vec::new(),
+            margins,
+            separation,
+            texture_region_size,
+            tiles,
+            fields,
+            assigns,
         })
     }
+
+    /// Replaces the tiles and returns the previous ones. Properties that
+    /// tilecutter doesn't manage are moved over to the new tiles, as long as
+    /// they have the same position or terrain data.
+    pub fn replace_tiles(&mut self, tiles: Vec<Tile>) -> Vec<Tile> {
+        let old_tiles = std::mem::replace(&mut self.tiles, tiles);
+
+        let mut old_by_position = HashMap::new();
+        let mut old_by_terrain = HashMap::new();
+        for tile in &old_tiles {
+            old_by_position.insert(tile.position, tile);
+
+            if tile.terrain_set.is_some() {
+                old_by_terrain.entry(tile.terrain_key()).or_insert(tile);
+            }
+        }
+
+        for tile in &mut self.tiles {
+            let old_tile = old_by_position
+                .get(&tile.position)
+                .filter(|old_tile| old_tile.terrain_key() == tile.terrain_key())
+                .or_else(|| {
+                    tile.terrain_set
+                        .and_then(|_| old_by_terrain.get(&tile.terrain_key()))
+                });
+
+            if let Some(old_tile) = old_tile {
+                tile.properties.clone_from(&old_tile.properties);
+            }
+        }
+
+        old_tiles
+    }
+
+    /// Makes the atlas source's tag, where `page` is its index among the
+    /// atlas sources that tilecutter manages.
+    fn to_tag(&self, config: &Config, material_ids: &HashMap<&str, String>, page: usize) -> Tag {
+        let mut fields = vec![
+            Field {
+                identifier: "type".into(),
+                value: Value::String("TileSetAtlasSource".into()),
+            },
+            Field {
+                identifier: "id".into(),
+                value: Value::String(self.id.clone()),
+            },
+        ];
+        fields.extend(self.fields.iter().cloned());
+
+        let mut generated = vec![TagAssign {
+            assign: "texture".into(),
+            value: Value::ExtResource(self.texture.clone()),
+        }];
+
+        // Godot leaves out zero margins and separation.
+        if self.margins != Vector2i::default() {
+            generated.push(TagAssign {
+                assign: "margins".into(),
+                value: Value::Vector2i(self.margins),
+            });
+        }
+
+        if self.separation != Vector2i::default() {
+            generated.push(TagAssign {
+                assign: "separation".into(),
+                value: Value::Vector2i(self.separation),
+            });
+        }
+
+        generated.push(TagAssign {
+            assign: "texture_region_size".into(),
+            value: Value::Vector2i(self.texture_region_size),
+        });
+
+        let mut assigns = merge_assigns(&self.assigns, generated, ATLAS_SOURCE_PROPERTY_ORDER);
+
+        for tile in &self.tiles {
+            tile.append_assigns(&mut assigns, config, material_ids, page);
+        }
+
+        Tag {
+            name: "sub_resource".into(),
+            fields,
+            assigns,
+        }
+    }
 }
 
-#[derive(Debug)]
-pub(crate) struct Tile {
+/// Splits a tile property path, such as `1:2/0/terrain`, into its atlas
+/// coordinates and the rest of the path.
+fn parse_tile_path(path: &str) -> Option<(Vector2i, &str)> {
+    let (coordinates, property) = path.split_once('/')?;
+    let (x, y) = coordinates.split_once(':')?;
+
+    Some((
+        Vector2i {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+        },
+        property,
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
     pub position: Vector2i,
+    /// The number of atlas cells the tile covers.
+    pub size_in_atlas: Vector2i,
     pub terrain_set: Option<u32>,
     pub terrain: Option<u32>,
     pub terrains_peering_bit: PeeringBit,
+    /// The probability of the tile being picked among tiles with the same
+    /// terrains. It's only managed by tilecutter when it's set.
+    pub probability: Option<f64>,
+    pub occluder_polygons: Vec<OccluderPolygon>,
+    pub physics_polygons: Vec<PhysicsPolygon>,
+    pub navigation_polygons: Vec<NavigationPolygon>,
+    /// Custom data values, by custom data layer index.
+    pub(crate) custom_data: Vec<(usize, Value)>,
+    pub animation: Option<TileAnimation>,
+    /// Alternatives `1`, `2`, and so on. When there are any, they replace the
+    /// alternatives from the previous export.
+    pub alternatives: Vec<AlternativeTile>,
+    /// Rendering properties for all of the tile's alternatives.
+    pub rendering: TileRendering,
+    /// Properties that tilecutter doesn't manage, with paths relative to
+    /// `x:y/`. These are kept from the previous export.
+    pub(crate) properties: Vec<TagAssign>,
+}
+
+/// An alternative of a tile, with the same terrains, polygons and custom data.
+#[derive(Debug, Clone)]
+pub struct AlternativeTile {
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub transpose: bool,
+    pub(crate) modulate: Option<Color>,
+    /// The tile's peering bits, mirrored to match the flips.
+    pub terrains_peering_bit: PeeringBit,
+    /// Overrides the tile's probability.
+    pub probability: Option<f64>,
+}
+
+/// Tile rendering properties, which are only managed by tilecutter when
+/// they're set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileRendering {
+    pub texture_origin: Option<Vector2i>,
+    pub z_index: Option<i64>,
+    pub y_sort_origin: Option<i64>,
+    /// The Godot path of a material.
+    pub material: Option<String>,
+}
+
+impl TileRendering {
+    /// Checks if a property, with a path relative to `x:y/id/`, is set.
+    fn is_set(&self, property: &str) -> bool {
+        match property {
+            "texture_origin" => self.texture_origin.is_some(),
+            "z_index" => self.z_index.is_some(),
+            "y_sort_origin" => self.y_sort_origin.is_some(),
+            "material" => self.material.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// An occluder polygon, with pixel coordinates relative to the tile's center.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccluderPolygon {
+    pub layer: usize,
+    pub points: Vec<[f64; 2]>,
+}
+
+/// A collision polygon, with pixel coordinates relative to the tile's center.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicsPolygon {
+    pub layer: usize,
+    pub points: Vec<[f64; 2]>,
+}
+
+/// A navigation mesh, with pixel coordinates relative to the tile's center.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavigationPolygon {
+    pub layer: usize,
+    pub vertices: Vec<[f64; 2]>,
+    /// Convex polygons, as indices into `vertices`.
+    pub polygons: Vec<Vec<usize>>,
+    /// The outlines that Godot's editor shows and edits. The polygons are used
+    /// as they are, even if they don't match the outlines.
+    pub outlines: Vec<Vec<[f64; 2]>>,
+}
+
+/// A tile animation, where the frames are in the cells after the tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileAnimation {
+    /// The number of frames per row, or 0 for a single row.
+    pub columns: u32,
+    /// The number of cells between the frames.
+    pub separation: Vector2i,
+    pub speed: f64,
+    pub frame_durations: Vec<f64>,
+}
+
+impl TileAnimation {
+    /// The offset, in cells, from the tile to one of its frames, where each
+    /// frame covers `size_in_atlas` cells.
+    pub fn frame_offset(&self, frame: usize, size_in_atlas: Vector2i) -> Vector2i {
+        let frame = frame as i64;
+        let (column, row) = match i64::from(self.columns) {
+            0 => (frame, 0),
+            columns => (frame % columns, frame / columns),
+        };
+
+        Vector2i {
+            x: column * (size_in_atlas.x + self.separation.x),
+            y: row * (size_in_atlas.y + self.separation.y),
+        }
+    }
+}
+
+impl Default for TileAnimation {
+    fn default() -> Self {
+        Self {
+            columns: 0,
+            separation: Vector2i::default(),
+            speed: 1.0,
+            frame_durations: vec![1.0],
+        }
+    }
 }
 
 impl Tile {
-    fn append_assigns(&self, assigns: &mut Vec<TagAssign>) {
-        let path = format!("{}:{}/0", self.position.x, self.position.y);
+    pub fn terrain_key(&self) -> (Option<u32>, Option<u32>, &PeeringBit) {
+        (self.terrain_set, self.terrain, &self.terrains_peering_bit)
+    }
+
+    /// The ID of a generated sub-resource. Tiles in additional atlas sources
+    /// have the same positions as other tiles, so their IDs include the page.
+    fn sub_resource_id(&self, ty: &str, layer: usize, page: usize) -> String {
+        let Vector2i { x, y } = self.position;
+
+        match page {
+            0 => format!("{ty}_{x}_{y}_{layer}"),
+            page => format!("{ty}_{page}_{x}_{y}_{layer}"),
+        }
+    }
+
+    /// The generated sub-resources that the tile's properties use.
+    fn sub_resource_tags(&self, page: usize) -> Vec<Tag> {
+        let occluders = self.occluder_polygons.iter().map(|polygon| {
+            sub_resource_tag(
+                "OccluderPolygon2D",
+                self.sub_resource_id("OccluderPolygon2D", polygon.layer, page),
+                vec![TagAssign {
+                    assign: "polygon".into(),
+                    value: points_to_value(&polygon.points),
+                }],
+            )
+        });
+
+        let navigation = self.navigation_polygons.iter().map(|polygon| {
+            let polygons = polygon
+                .polygons
+                .iter()
+                .map(|indices| {
+                    Value::Integers(
+                        IntegerType::PackedInt32Array,
+                        indices.iter().map(|&index| index as i64).collect(),
+                    )
+                })
+                .collect();
+
+            let mut assigns = vec![
+                TagAssign {
+                    assign: "vertices".into(),
+                    value: points_to_value(&polygon.vertices),
+                },
+                TagAssign {
+                    assign: "polygons".into(),
+                    value: Value::Array(polygons),
+                },
+            ];
+
+            if !polygon.outlines.is_empty() {
+                assigns.push(TagAssign {
+                    assign: "outlines".into(),
+                    value: Value::Array(
+                        polygon
+                            .outlines
+                            .iter()
+                            .map(|outline| points_to_value(outline))
+                            .collect(),
+                    ),
+                });
+            }
 
-        assigns.push(TagAssign {
-            assign: path.clone(),
-            value: Value::Integer(0),
+            sub_resource_tag(
+                "NavigationPolygon",
+                self.sub_resource_id("NavigationPolygon", polygon.layer, page),
+                assigns,
+            )
         });
 
+        occluders.chain(navigation).collect()
+    }
+
+    /// Sets a property from its path, relative to `x:y/`. Returns `false` if
+    /// the property isn't managed by tilecutter.
+    fn set_property(&mut self, property: &str, value: &Value) -> Result<bool> {
+        if let Some(property) = property.strip_prefix("animation_") {
+            return self.set_animation_property(property, value);
+        }
+
+        if property == "size_in_atlas" {
+            let &Value::Vector2i(size_in_atlas) = value else {
+                bail!("expected a 'Vector2i', but found {value:?}");
+            };
+
+            self.size_in_atlas = size_in_atlas;
+            return Ok(true);
+        }
+
+        let field = match property {
+            "0" => return Ok(true),
+            "0/terrain_set" => &mut self.terrain_set,
+            "0/terrain" => &mut self.terrain,
+            property => match property.strip_prefix("0/terrains_peering_bit/") {
+                Some(name) => match self.terrains_peering_bit.get_mut(name) {
+                    Some(bit) => bit,
+                    // Peering bits are always generated, so unknown ones are dropped.
+                    None => return Ok(true),
+                },
+                None => return Ok(false),
+            },
+        };
+
+        let &Value::Integer(value) = value else {
+            bail!("expected an integer, but found {value:?}");
+        };
+
+        *field = Some(
+            u32::try_from(value)
+                .with_context(|| format!("expected a non-negative integer, but found {value}"))?,
+        );
+
+        Ok(true)
+    }
+
+    /// Sets an animation property from its path, relative to
+    /// `x:y/animation_`. Returns `false` if the property isn't managed by
+    /// tilecutter.
+    fn set_animation_property(&mut self, property: &str, value: &Value) -> Result<bool> {
+        let frame = property
+            .strip_prefix("frame_")
+            .and_then(|property| property.strip_suffix("/duration"))
+            .and_then(|frame| frame.parse::<usize>().ok());
+
+        if frame.is_none()
+            && !matches!(
+                property,
+                "columns" | "separation" | "speed" | "frames_count"
+            )
+        {
+            return Ok(false);
+        }
+
+        let animation = self.animation.get_or_insert_with(Default::default);
+
+        match (property, value) {
+            ("columns", &Value::Integer(columns)) => {
+                animation.columns = u32::try_from(columns).with_context(|| {
+                    format!("expected a non-negative integer, but found {columns}")
+                })?;
+            }
+            ("separation", &Value::Vector2i(separation)) => animation.separation = separation,
+            ("speed", value) => animation.speed = number_value(value)?,
+            ("frames_count", &Value::Integer(count)) => {
+                let count = usize::try_from(count).with_context(|| {
+                    format!("expected a non-negative integer, but found {count}")
+                })?;
+                animation.frame_durations.resize(count, 1.0);
+            }
+            (_, value) => {
+                let Some(frame) = frame else {
+                    bail!("unexpected value {value:?}");
+                };
+
+                if frame >= animation.frame_durations.len() {
+                    animation.frame_durations.resize(frame + 1, 1.0);
+                }
+
+                animation.frame_durations[frame] = number_value(value)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Checks if a kept property, with a path relative to `x:y/`, is replaced
+    /// by generated properties.
+    fn is_managed_property(&self, property: &str, managed_prefixes: &[&str]) -> bool {
+        let (alternative, path) = property.split_once('/').unwrap_or((property, ""));
+        let Ok(alternative) = alternative.parse::<usize>() else {
+            return false;
+        };
+
+        let probability = match alternative {
+            0 => self.probability,
+            _ if self.alternatives.is_empty() => return false,
+            _ => match self.alternatives.get(alternative - 1) {
+                Some(alternative) => alternative.probability.or(self.probability),
+                None => return true,
+            },
+        };
+
+        (alternative > 0
+            && (path.is_empty()
+                || matches!(
+                    path,
+                    "flip_h" | "flip_v" | "transpose" | "modulate" | "terrain_set" | "terrain"
+                )
+                || path.starts_with("terrains_peering_bit/")))
+            || (probability.is_some() && path == "probability")
+            || self.rendering.is_set(path)
+            || managed_prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+    }
+
+    /// Appends the tile's properties, where `material_ids` are the IDs of the
+    /// external resources for the material paths, and `page` is the index of
+    /// the tile's atlas source.
+    fn append_assigns(
+        &self,
+        assigns: &mut Vec<TagAssign>,
+        config: &Config,
+        material_ids: &HashMap<&str, String>,
+        page: usize,
+    ) {
+        let tile_path = format!("{}:{}", self.position.x, self.position.y);
+
+        let managed_prefixes = managed_layer_prefixes(config);
+        let properties = self
+            .properties
+            .iter()
+            .filter(|property| !self.is_managed_property(&property.assign, &managed_prefixes));
+
+        // Tile properties, such as animations, come before the alternatives.
+        let (tile_properties, mut alternative_properties): (Vec<_>, Vec<_>) = properties
+            .partition(|property| !property.assign.starts_with(|c: char| c.is_ascii_digit()));
+
+        for property in tile_properties {
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/{}", property.assign),
+                value: property.value.clone(),
+            });
+        }
+
+        // Godot leaves out the default size.
+        if self.size_in_atlas != (Vector2i { x: 1, y: 1 }) {
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/size_in_atlas"),
+                value: Value::Vector2i(self.size_in_atlas),
+            });
+        }
+
+        if let Some(animation) = &self.animation {
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/animation_columns"),
+                value: Value::Integer(animation.columns.into()),
+            });
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/animation_separation"),
+                value: Value::Vector2i(animation.separation),
+            });
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/animation_speed"),
+                value: Value::Double(animation.speed),
+            });
+
+            for (frame, &duration) in animation.frame_durations.iter().enumerate() {
+                assigns.push(TagAssign {
+                    assign: format!("{tile_path}/animation_frame_{frame}/duration"),
+                    value: Value::Double(duration),
+                });
+            }
+        }
+
+        for id in 0..=self.alternatives.len() {
+            let path = format!("{tile_path}/{id}");
+            let alternative = id.checked_sub(1).map(|index| &self.alternatives[index]);
+
+            assigns.push(TagAssign {
+                assign: path.clone(),
+                value: Value::Integer(id as i64),
+            });
+
+            if let Some(alternative) = alternative {
+                for (name, enabled) in [
+                    ("flip_h", alternative.flip_h),
+                    ("flip_v", alternative.flip_v),
+                    ("transpose", alternative.transpose),
+                ] {
+                    if enabled {
+                        assigns.push(TagAssign {
+                            assign: format!("{path}/{name}"),
+                            value: Value::Bool(true),
+                        });
+                    }
+                }
+            }
+
+            if let Some(texture_origin) = self.rendering.texture_origin {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/texture_origin"),
+                    value: Value::Vector2i(texture_origin),
+                });
+            }
+
+            if let Some(modulate) =
+                alternative.and_then(|alternative| alternative.modulate.as_ref())
+            {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/modulate"),
+                    value: Value::Color(modulate.clone()),
+                });
+            }
+
+            if let Some(material) = &self.rendering.material {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/material"),
+                    value: Value::ExtResource(material_ids[&**material].clone()),
+                });
+            }
+
+            if let Some(z_index) = self.rendering.z_index {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/z_index"),
+                    value: Value::Integer(z_index),
+                });
+            }
+
+            if let Some(y_sort_origin) = self.rendering.y_sort_origin {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/y_sort_origin"),
+                    value: Value::Integer(y_sort_origin),
+                });
+            }
+
+            let (terrains_peering_bit, probability) = match alternative {
+                Some(alternative) => (
+                    &alternative.terrains_peering_bit,
+                    alternative.probability.or(self.probability),
+                ),
+                None => (&self.terrains_peering_bit, self.probability),
+            };
+            self.append_tile_data_assigns(
+                assigns,
+                &path,
+                terrains_peering_bit,
+                probability,
+                config,
+                page,
+            );
+
+            let id = id.to_string();
+            let (kept_properties, other_properties): (Vec<_>, Vec<_>) =
+                alternative_properties.into_iter().partition(|property| {
+                    property
+                        .assign
+                        .split_once('/')
+                        .map_or(property.assign == id, |(alternative, _)| alternative == id)
+                });
+            alternative_properties = other_properties;
+
+            for property in kept_properties {
+                assigns.push(TagAssign {
+                    assign: format!("{tile_path}/{}", property.assign),
+                    value: property.value.clone(),
+                });
+            }
+        }
+
+        for property in alternative_properties {
+            assigns.push(TagAssign {
+                assign: format!("{tile_path}/{}", property.assign),
+                value: property.value.clone(),
+            });
+        }
+    }
+
+    /// Appends the generated properties of one of the tile's alternatives,
+    /// where `path` is `x:y/id`.
+    fn append_tile_data_assigns(
+        &self,
+        assigns: &mut Vec<TagAssign>,
+        path: &str,
+        terrains_peering_bit: &PeeringBit,
+        probability: Option<f64>,
+        config: &Config,
+        page: usize,
+    ) {
         if let Some(terrain_set) = self.terrain_set {
             assigns.push(TagAssign {
                 assign: format!("{path}/terrain_set"),
@@ -372,56 +1787,287 @@ impl Tile {
             });
         }
 
-        if let Some(bottom_right_side) = self.terrains_peering_bit.bottom_right_side {
+        // Godot leaves out the default probability.
+        if let Some(probability) = probability.filter(|&probability| probability != 1.0) {
             assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/bottom_right_side"),
-                value: Value::Integer(bottom_right_side as i64),
+                assign: format!("{path}/probability"),
+                value: Value::Double(probability),
             });
         }
 
-        if let Some(bottom_side) = self.terrains_peering_bit.bottom_side {
+        for polygon in &self.occluder_polygons {
+            if polygon.layer >= config.occlusion_layers.len() {
+                continue;
+            }
+
             assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/bottom_side"),
-                value: Value::Integer(bottom_side as i64),
+                assign: format!("{path}/occlusion_layer_{}/polygon", polygon.layer),
+                value: Value::SubResource(self.sub_resource_id(
+                    "OccluderPolygon2D",
+                    polygon.layer,
+                    page,
+                )),
             });
         }
 
-        if let Some(bottom_left_side) = self.terrains_peering_bit.bottom_left_side {
+        let mut polygon_counts = vec![0; config.physics_layers.len()];
+        for polygon in &self.physics_polygons {
+            let Some(polygon_count) = polygon_counts.get_mut(polygon.layer) else {
+                continue;
+            };
+
             assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/bottom_left_side"),
-                value: Value::Integer(bottom_left_side as i64),
+                assign: format!(
+                    "{path}/physics_layer_{}/polygon_{polygon_count}/points",
+                    polygon.layer
+                ),
+                value: points_to_value(&polygon.points),
             });
+            *polygon_count += 1;
         }
 
-        if let Some(top_left_side) = self.terrains_peering_bit.top_left_side {
-            assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/top_left_side"),
-                value: Value::Integer(top_left_side as i64),
-            });
+        for (neighbor, terrain) in terrains_peering_bit.iter() {
+            if let Some(terrain) = terrain {
+                assigns.push(TagAssign {
+                    assign: format!("{path}/terrains_peering_bit/{}", neighbor.name()),
+                    value: Value::Integer(terrain as i64),
+                });
+            }
         }
 
-        if let Some(top_side) = self.terrains_peering_bit.top_side {
+        for polygon in &self.navigation_polygons {
+            if polygon.layer >= config.navigation_layers.len() {
+                continue;
+            }
+
             assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/top_side"),
-                value: Value::Integer(top_side as i64),
+                assign: format!("{path}/navigation_layer_{}/polygon", polygon.layer),
+                value: Value::SubResource(self.sub_resource_id(
+                    "NavigationPolygon",
+                    polygon.layer,
+                    page,
+                )),
             });
         }
 
-        if let Some(top_right_side) = self.terrains_peering_bit.top_right_side {
+        for (layer, value) in &self.custom_data {
+            if *layer >= config.custom_data_layers.len() {
+                continue;
+            }
+
             assigns.push(TagAssign {
-                assign: format!("{path}/terrains_peering_bit/top_right_side"),
-                value: Value::Integer(top_right_side as i64),
+                assign: format!("{path}/custom_data_{layer}"),
+                value: value.clone(),
             });
         }
     }
 }
 
-#[derive(Debug, Default)]
-pub(crate) struct PeeringBit {
-    pub bottom_right_side: Option<u32>,
-    pub bottom_side: Option<u32>,
-    pub bottom_left_side: Option<u32>,
-    pub top_left_side: Option<u32>,
-    pub top_side: Option<u32>,
-    pub top_right_side: Option<u32>,
+/// Converts custom data values from the config to the types of their layers,
+/// and pairs them with the layer indices.
+pub(crate) fn custom_data_values(
+    values: &BTreeMap<String, toml::Value>,
+    layers: &[CustomDataLayerConfig],
+) -> Result<Vec<(usize, Value)>> {
+    let mut custom_data = values
+        .iter()
+        .map(|(name, value)| {
+            let Some(layer) = layers.iter().position(|layer| &layer.name == name) else {
+                bail!("custom data layer {name:?} is not in 'custom_data_layers'");
+            };
+
+            let value = custom_data_value(layers[layer].ty, value)
+                .with_context(|| format!("invalid value for custom data layer {name:?}"))?;
+
+            Ok((layer, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    custom_data.sort_by_key(|&(layer, _)| layer);
+
+    Ok(custom_data)
+}
+
+fn custom_data_value(ty: CustomDataType, value: &toml::Value) -> Result<Value> {
+    let as_number = |value: &toml::Value| match *value {
+        toml::Value::Integer(value) => Some(value as f64),
+        toml::Value::Float(value) => Some(value),
+        _ => None,
+    };
+    let numbers = value
+        .as_array()
+        .and_then(|values| values.iter().map(as_number).collect::<Option<Vec<_>>>());
+    let integers = value.as_array().and_then(|values| {
+        values
+            .iter()
+            .map(toml::Value::as_integer)
+            .collect::<Option<Vec<_>>>()
+    });
+
+    let result = match ty {
+        CustomDataType::Bool => value.as_bool().map(Value::Bool),
+        CustomDataType::Int => value.as_integer().map(Value::Integer),
+        CustomDataType::Float => as_number(value).map(Value::Double),
+        CustomDataType::String => value.as_str().map(|value| Value::String(value.into())),
+        CustomDataType::StringName => value.as_str().map(|value| Value::StringName(value.into())),
+        CustomDataType::Vector2 => match numbers.as_deref() {
+            Some(&[x, y]) => Some(Value::Floats(FloatType::Vector2, vec![x, y])),
+            _ => None,
+        },
+        CustomDataType::Vector2i => match integers.as_deref() {
+            Some(&[x, y]) => Some(Value::Vector2i(Vector2i { x, y })),
+            _ => None,
+        },
+        CustomDataType::Color => numbers.as_deref().and_then(rgba).map(Value::Color),
+    };
+
+    result.ok_or_else(|| {
+        anyhow!(
+            "expected a value of type {ty:?}, but found a {}",
+            value.type_str()
+        )
+    })
+}
+
+/// Makes a color from three or four numbers between 0 and 1.
+pub(crate) fn rgba(components: &[f64]) -> Option<Color> {
+    match *components {
+        [r, g, b] => Some(Color::Rgba(r, g, b, 1.0)),
+        [r, g, b, a] => Some(Color::Rgba(r, g, b, a)),
+        _ => None,
+    }
+}
+
+fn sub_resource_tag(ty: &str, id: String, assigns: Vec<TagAssign>) -> Tag {
+    Tag {
+        name: "sub_resource".into(),
+        fields: vec![
+            Field {
+                identifier: "type".into(),
+                value: Value::String(ty.into()),
+            },
+            Field {
+                identifier: "id".into(),
+                value: Value::String(id),
+            },
+        ],
+        assigns,
+    }
+}
+
+fn number_value(value: &Value) -> Result<f64> {
+    match *value {
+        Value::Double(value) => Ok(value),
+        Value::Integer(value) => Ok(value as f64),
+        ref value => bail!("expected a number, but found {value:?}"),
+    }
+}
+
+fn points_to_value(points: &[[f64; 2]]) -> Value {
+    Value::Floats(
+        FloatType::PackedVector2Array,
+        points.iter().flatten().copied().collect(),
+    )
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct PeeringBit {
+    bits: [Option<u32>; CellNeighbor::ALL.len()],
+}
+
+impl PeeringBit {
+    pub fn get(&self, neighbor: CellNeighbor) -> Option<u32> {
+        self.bits[neighbor as usize]
+    }
+
+    pub fn set(&mut self, neighbor: CellNeighbor, terrain: Option<u32>) {
+        self.bits[neighbor as usize] = terrain;
+    }
+
+    /// Iterates over all peering bits, in Godot's order.
+    pub fn iter(&self) -> impl Iterator<Item = (CellNeighbor, Option<u32>)> + '_ {
+        CellNeighbor::ALL
+            .into_iter()
+            .map(|neighbor| (neighbor, self.get(neighbor)))
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Option<u32>> {
+        let neighbor = CellNeighbor::from_name(name)?;
+        Some(&mut self.bits[neighbor as usize])
+    }
+}
+
+/// The sides and corners of a cell that can have terrain peering bits, in
+/// the same order as Godot's `TileSet.CellNeighbor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CellNeighbor {
+    RightSide,
+    RightCorner,
+    BottomRightSide,
+    BottomRightCorner,
+    BottomSide,
+    BottomCorner,
+    BottomLeftSide,
+    BottomLeftCorner,
+    LeftSide,
+    LeftCorner,
+    TopLeftSide,
+    TopLeftCorner,
+    TopSide,
+    TopCorner,
+    TopRightSide,
+    TopRightCorner,
+}
+
+impl CellNeighbor {
+    pub const ALL: [Self; 16] = [
+        Self::RightSide,
+        Self::RightCorner,
+        Self::BottomRightSide,
+        Self::BottomRightCorner,
+        Self::BottomSide,
+        Self::BottomCorner,
+        Self::BottomLeftSide,
+        Self::BottomLeftCorner,
+        Self::LeftSide,
+        Self::LeftCorner,
+        Self::TopLeftSide,
+        Self::TopLeftCorner,
+        Self::TopSide,
+        Self::TopCorner,
+        Self::TopRightSide,
+        Self::TopRightCorner,
+    ];
+
+    /// The Godot property name, as in `terrains_peering_bit/<name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RightSide => "right_side",
+            Self::RightCorner => "right_corner",
+            Self::BottomRightSide => "bottom_right_side",
+            Self::BottomRightCorner => "bottom_right_corner",
+            Self::BottomSide => "bottom_side",
+            Self::BottomCorner => "bottom_corner",
+            Self::BottomLeftSide => "bottom_left_side",
+            Self::BottomLeftCorner => "bottom_left_corner",
+            Self::LeftSide => "left_side",
+            Self::LeftCorner => "left_corner",
+            Self::TopLeftSide => "top_left_side",
+            Self::TopLeftCorner => "top_left_corner",
+            Self::TopSide => "top_side",
+            Self::TopCorner => "top_corner",
+            Self::TopRightSide => "top_right_side",
+            Self::TopRightCorner => "top_right_corner",
+        }
+    }
+
+    pub fn is_corner(self) -> bool {
+        self.name().ends_with("_corner")
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|neighbor| neighbor.name() == name)
+    }
 }
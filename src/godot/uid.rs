@@ -0,0 +1,39 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::SystemTime,
+};
+
+/// The characters Godot uses when encoding resource IDs as text.
+const UID_CHARACTERS: &[u8; 34] = b"abcdefghijklmnopqrstuvwxy012345678";
+
+/// Generates a new random resource UID, on the `uid://...` text format.
+pub fn generate_uid() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+
+    // Godot's IDs are non-negative 64 bit integers.
+    uid_to_text(hasher.finish() & 0x7FFF_FFFF_FFFF_FFFF)
+}
+
+fn uid_to_text(mut id: u64) -> String {
+    let mut characters = Vec::new();
+
+    loop {
+        let base = UID_CHARACTERS.len() as u64;
+        characters.push(UID_CHARACTERS[(id % base) as usize]);
+        id /= base;
+
+        if id == 0 {
+            break;
+        }
+    }
+
+    characters.reverse();
+
+    let text = String::from_utf8(characters).expect("UID characters should be ASCII");
+    format!("uid://{text}")
+}
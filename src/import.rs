@@ -0,0 +1,310 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::{GenericImage, GenericImageView, RgbaImage};
+
+use crate::{
+    config::{AnimationConfig, Config, GodotConfig, TileConfig, TileSetConfig},
+    godot::{
+        self,
+        resource::{read_tile_shape, AtlasSource, TileSetResource},
+        Vector2i,
+    },
+    godot_path_to_absolute,
+    init::write_config,
+    Error,
+};
+
+/// Cuts the atlas texture of an existing tile set resource into tile images,
+/// and writes a config file that puts them back at the same atlas positions.
+///
+/// The Godot project directory is found by looking for `project.godot` next to
+/// the resource or in one of its parent directories, unless it's provided.
+pub fn import_project(
+    resource_path: &Path,
+    config_path: &Path,
+    godot_project_path: Option<&Path>,
+) -> Result<Config, Error> {
+    if config_path.exists() {
+        return Err(Error::Config(anyhow!("{config_path:?} already exists")));
+    }
+
+    let resource_path = resource_path
+        .canonicalize()
+        .with_context(|| format!("could not find {resource_path:?}"))
+        .map_err(Error::Resource)?;
+
+    let godot_project_path = match godot_project_path {
+        Some(path) => path
+            .canonicalize()
+            .with_context(|| format!("could not find {path:?}"))
+            .map_err(Error::Resource)?,
+        None => find_godot_project(&resource_path).map_err(Error::Resource)?,
+    };
+
+    let godot_file = godot::parse_file(&resource_path)
+        .with_context(|| format!("could not parse {resource_path:?} as a '*.tres' file"))
+        .map_err(Error::Resource)?;
+    let (tile_shape, tile_layout, tile_offset_axis) =
+        read_tile_shape(&godot_file).map_err(Error::Resource)?;
+    let resource = TileSetResource::init_from_file(godot_file)
+        .context("unexpected '*.tres' file content")
+        .map_err(Error::Resource)?;
+
+    let tile_set_path = to_godot_path(&godot_project_path, &resource_path)
+        .with_context(|| format!("expected {resource_path:?} to be in the Godot project"))
+        .map_err(Error::Resource)?;
+
+    // Configured tiles are always in the first atlas source. Additional atlas
+    // sources can only be skipped if their tiles are generated by terrains.
+    let additional_sources = &resource.atlas_sources[1..];
+    if let Some(source) = additional_sources.iter().find(|source| {
+        source
+            .tile_set_atlas_source
+            .tiles
+            .iter()
+            .any(|tile| tile.terrain_set.is_none())
+    }) {
+        return Err(Error::Resource(anyhow!(
+            "atlas source {} has tiles without terrains, but only the first atlas source can be imported",
+            source.source_id
+        )));
+    }
+
+    if !additional_sources.is_empty() {
+        eprintln!(
+            "skipping {} additional atlas sources, which only have terrain tiles",
+            additional_sources.len()
+        );
+    }
+
+    let AtlasSource {
+        texture_resource,
+        tile_set_atlas_source: atlas_source,
+        ..
+    } = &resource.atlas_sources[0];
+    let tile_size = vector_to_size(atlas_source.texture_region_size)
+        .context("invalid 'texture_region_size'")
+        .map_err(Error::Resource)?;
+    let margins = vector_to_size(atlas_source.margins)
+        .context("invalid 'margins'")
+        .map_err(Error::Resource)?;
+    let separation = vector_to_size(atlas_source.separation)
+        .context("invalid 'separation'")
+        .map_err(Error::Resource)?;
+
+    let texture_path = godot_path_to_absolute(&godot_project_path, &texture_resource.path)
+        .map_err(Error::Resource)?;
+    let texture = File::open(&texture_path)
+        .with_context(|| format!("could not open {texture_path:?}"))
+        .and_then(|image_file| {
+            image::load(BufReader::new(image_file), image::ImageFormat::Png)
+                .with_context(|| format!("could not load {texture_path:?}"))
+        })
+        .map_err(Error::Resource)?
+        .into_rgba8();
+
+    let mut atlas_tiles = atlas_source.tiles.iter().collect::<Vec<_>>();
+    atlas_tiles.sort_by_key(|tile| (tile.position.y, tile.position.x));
+
+    let terrain_tile_count = resource
+        .atlas_sources
+        .iter()
+        .flat_map(|source| &source.tile_set_atlas_source.tiles)
+        .filter(|tile| tile.terrain_set.is_some())
+        .count();
+    if terrain_tile_count > 0 {
+        eprintln!(
+            "{terrain_tile_count} tiles have terrain data, which will be replaced by the next export"
+        );
+    }
+
+    let config_directory_path = match config_path.parent() {
+        Some(path) if !path.as_os_str().is_empty() => path,
+        _ => Path::new("."),
+    };
+    let tiles_directory_path = config_directory_path.join("tiles");
+
+    let mut tiles = Vec::new();
+    for tile in atlas_tiles {
+        let [x, y] = vector_to_size(tile.position)
+            .context("invalid atlas position")
+            .map_err(Error::Resource)?;
+
+        let name = format!("tile_{x}_{y}");
+        let path = tiles_directory_path.join(format!("{name}.png"));
+        if path.exists() {
+            return Err(Error::Config(anyhow!("{path:?} already exists")));
+        }
+
+        let size = vector_to_size(tile.size_in_atlas)
+            .context("invalid 'size_in_atlas'")
+            .map_err(Error::Resource)?;
+
+        // Animation frames are put side by side in the tile image.
+        let frame_cells = match &tile.animation {
+            Some(animation) => (0..animation.frame_durations.len())
+                .map(|frame| {
+                    vector_to_size(animation.frame_offset(frame, tile.size_in_atlas))
+                        .map(|[offset_x, offset_y]| [x + offset_x, y + offset_y])
+                })
+                .collect::<Result<Vec<_>>>()
+                .context("invalid animation layout")
+                .map_err(Error::Resource)?,
+            None => vec![[x, y]],
+        };
+
+        // Multi-cell tiles include the separation between their cells.
+        let [width, height] =
+            [0, 1].map(|axis| size[axis] * (tile_size[axis] + separation[axis]) - separation[axis]);
+
+        let mut image = RgbaImage::new(width * frame_cells.len() as u32, height);
+        for (frame, [cell_x, cell_y]) in frame_cells.into_iter().enumerate() {
+            let left = margins[0] + cell_x * (tile_size[0] + separation[0]);
+            let top = margins[1] + cell_y * (tile_size[1] + separation[1]);
+
+            if left + width > texture.width() || top + height > texture.height() {
+                return Err(Error::Resource(anyhow!(
+                    "the tile at ({cell_x}, {cell_y}) is outside the texture"
+                )));
+            }
+
+            image
+                .copy_from(
+                    &*texture.view(left, top, width, height),
+                    frame as u32 * width,
+                    0,
+                )
+                .expect("the frame should fit in the tile image");
+        }
+
+        let animation = match &tile.animation {
+            Some(animation) => Some(AnimationConfig {
+                frames: animation.frame_durations.clone(),
+                speed: animation.speed,
+                columns: animation.columns,
+                separation: vector_to_size(animation.separation)
+                    .context("invalid 'animation_separation'")
+                    .map_err(Error::Resource)?,
+            }),
+            None => None,
+        };
+
+        tiles.push((
+            TileConfig {
+                name,
+                position: [x, y],
+                size,
+                collision: Vec::new(),
+                navigation: Vec::new(),
+                custom_data: Default::default(),
+                animation,
+                alternatives: Vec::new(),
+                rendering: Default::default(),
+            },
+            path,
+            image,
+        ));
+    }
+
+    fs::create_dir_all(&tiles_directory_path)
+        .context("could not create the tiles directory")
+        .map_err(Error::Write)?;
+
+    for (_, path, image) in &tiles {
+        image
+            .save_with_format(path, image::ImageFormat::Png)
+            .with_context(|| format!("could not write {path:?}"))
+            .map_err(Error::Write)?;
+    }
+
+    let project_path = config_directory_path
+        .canonicalize()
+        .context("could not find the config directory")
+        .map(|config_directory_path| relative_path(&config_directory_path, &godot_project_path))
+        .map_err(Error::Write)?;
+
+    let config = Config {
+        tile_set: TileSetConfig {
+            tile_size,
+            tile_shape,
+            tile_layout,
+            tile_offset_axis,
+            margins,
+            separation,
+            extrusion: 0,
+            packing: Default::default(),
+            power_of_two: false,
+            max_texture_size: None,
+        },
+        godot: GodotConfig {
+            project_path: project_path.to_string_lossy().into_owned(),
+            tile_set_path,
+        },
+        tiles: tiles.into_iter().map(|(tile, _, _)| tile).collect(),
+        terrain_sets: Vec::new(),
+        physics_layers: Vec::new(),
+        occlusion_layers: Vec::new(),
+        navigation_layers: Vec::new(),
+        custom_data_layers: Vec::new(),
+    };
+
+    write_config(config_path, &config)
+        .with_context(|| format!("could not write {config_path:?}"))
+        .map_err(Error::Write)?;
+
+    Ok(config)
+}
+
+fn find_godot_project(resource_path: &Path) -> Result<PathBuf> {
+    resource_path
+        .ancestors()
+        .skip(1)
+        .find(|path| path.join("project.godot").exists())
+        .map(Path::to_owned)
+        .ok_or_else(|| anyhow!("could not find a 'project.godot' file for {resource_path:?}"))
+}
+
+fn to_godot_path(project_path: &Path, path: &Path) -> Option<String> {
+    let relative_path = path.strip_prefix(project_path).ok()?;
+    let components = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(format!("res://{}", components.join("/")))
+}
+
+/// Makes a relative path from one absolute path to another.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let mut from_components = from.components().peekable();
+    let mut to_components = to.components().peekable();
+
+    while from_components.peek().is_some() && from_components.peek() == to_components.peek() {
+        from_components.next();
+        to_components.next();
+    }
+
+    let path = from_components
+        .map(|_| Component::ParentDir)
+        .chain(to_components)
+        .collect::<PathBuf>();
+
+    if path.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        path
+    }
+}
+
+fn vector_to_size(vector: Vector2i) -> Result<[u32; 2]> {
+    let (Ok(x), Ok(y)) = (u32::try_from(vector.x), u32::try_from(vector.y)) else {
+        bail!("expected ({}, {}) to not be negative", vector.x, vector.y);
+    };
+
+    Ok([x, y])
+}
@@ -0,0 +1,318 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
+
+use crate::{
+    config::{Config, TerrainSetConfig, TileLayout, TileOffsetAxis, TileShape},
+    godot::{self, resource::TileSetResource},
+    godot_path_to_absolute,
+    terrain::{generate_mask_image, MASK_TEMPLATE_NAME},
+    Error,
+};
+
+/// Creates a config file, the image directories, and a Godot tile set resource
+/// with a texture, so that the first export can run without Godot.
+pub fn init_project(config_path: &Path, config: &Config) -> Result<(), Error> {
+    if config_path.exists() {
+        return Err(Error::Config(anyhow!("{config_path:?} already exists")));
+    }
+
+    if !config.godot.tile_set_path.ends_with(".tres") {
+        return Err(Error::Config(anyhow!(
+            "expected 'tile_set_path' to be on the format 'res://Path/To/resource.tres'"
+        )));
+    }
+
+    create_files(config_path, config).map_err(Error::Write)
+}
+
+fn create_files(config_path: &Path, config: &Config) -> Result<()> {
+    let config_directory_path = config_path
+        .parent()
+        .expect("could not make a parent path for the config path");
+    let godot_project_path = config_directory_path.join(&config.godot.project_path);
+    let resource_path = godot_path_to_absolute(&godot_project_path, &config.godot.tile_set_path)?;
+
+    fs::create_dir_all(config_directory_path.join("tiles"))
+        .context("could not create the tiles directory")?;
+    fs::create_dir_all(config_directory_path.join("terrains"))
+        .context("could not create the terrains directory")?;
+
+    // A template for painting the mask by hand, with the sectors of the
+    // default terrain mode. It's only used after renaming it and setting it
+    // as the 'mask' of a terrain set.
+    let mask_path = config_directory_path
+        .join("terrains")
+        .join(MASK_TEMPLATE_NAME);
+    if !mask_path.exists() {
+        generate_mask_image(config, &TerrainSetConfig::default())?
+            .save_with_format(&mask_path, image::ImageFormat::Png)
+            .with_context(|| format!("could not write {mask_path:?}"))?;
+    }
+
+    if resource_path.exists() {
+        eprintln!("keeping the existing tile set resource {resource_path:?}");
+    } else {
+        write_resource(&godot_project_path, &resource_path, config)?;
+    }
+
+    write_config(config_path, config)
+        .with_context(|| format!("could not write {config_path:?}"))?;
+
+    Ok(())
+}
+
+fn write_resource(godot_project_path: &Path, resource_path: &Path, config: &Config) -> Result<()> {
+    if let Some(parent) = resource_path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("could not create {parent:?}"))?;
+    }
+
+    let godot_texture_path = config
+        .godot
+        .tile_set_path
+        .strip_suffix(".tres")
+        .map(|path| format!("{path}.png"))
+        .expect("the tile set path should end with '.tres'");
+    let texture_path = godot_path_to_absolute(godot_project_path, &godot_texture_path)?;
+    let texture_uid = godot::generate_uid();
+
+    // Godot keeps the UID from the import file when it imports the texture.
+    let import_path = texture_path.with_extension("png.import");
+    File::create(&import_path)
+        .and_then(|mut file| {
+            write!(
+                file,
+                "[remap]\n\nimporter=\"texture\"\ntype=\"CompressedTexture2D\"\nuid=\"{texture_uid}\"\n\n[deps]\n\nsource_file=\"{godot_texture_path}\"\n"
+            )
+        })
+        .with_context(|| format!("could not write {import_path:?}"))?;
+
+    let [tile_width, tile_height] = config.tile_set.tile_size;
+    RgbaImage::new(tile_width, tile_height)
+        .save_with_format(&texture_path, image::ImageFormat::Png)
+        .with_context(|| format!("could not write {texture_path:?}"))?;
+
+    let mut resource = TileSetResource::new(godot::generate_uid(), godot_texture_path, texture_uid);
+    resource.atlas_sources[0]
+        .tile_set_atlas_source
+        .texture_region_size = config.tile_set.tile_size.into();
+    resource
+        .print_to_file(resource_path, config)
+        .with_context(|| format!("could not write {resource_path:?}"))
+}
+
+pub(crate) fn write_config(path: &Path, config: &Config) -> Result<()> {
+    let [tile_width, tile_height] = config.tile_set.tile_size;
+
+    let tile_shape = match config.tile_set.tile_shape {
+        TileShape::Square => "square",
+        TileShape::Isometric => "isometric",
+        TileShape::HalfOffsetSquare => "half_offset_square",
+        TileShape::Hexagon => "hexagon",
+    };
+
+    let tile_layout = match config.tile_set.tile_layout {
+        TileLayout::Stacked => "stacked",
+        TileLayout::StackedOffset => "stacked_offset",
+        TileLayout::StairsRight => "stairs_right",
+        TileLayout::StairsDown => "stairs_down",
+        TileLayout::DiamondRight => "diamond_right",
+        TileLayout::DiamondDown => "diamond_down",
+    };
+
+    let tile_offset_axis = match config.tile_set.tile_offset_axis {
+        TileOffsetAxis::Horizontal => "horizontal",
+        TileOffsetAxis::Vertical => "vertical",
+    };
+
+    let tile_set = &config.tile_set;
+    let padding = if tile_set.margins == [0, 0] && tile_set.separation == [0, 0] {
+        "# Padding in the atlas image, with the tile edges repeated into it.\n# margins = [1, 1]\n# separation = [2, 2]\n# extrusion = 1\n".to_owned()
+    } else {
+        let [margin_x, margin_y] = tile_set.margins;
+        let [separation_x, separation_y] = tile_set.separation;
+        format!(
+            "margins = [{margin_x}, {margin_y}]\nseparation = [{separation_x}, {separation_y}]\nextrusion = {}\n",
+            tile_set.extrusion
+        )
+    };
+
+    let mut file = File::create(path)?;
+    write!(
+        file,
+        r#"[tile_set]
+tile_size = [{tile_width}, {tile_height}]
+tile_shape = "{tile_shape}"
+tile_layout = "{tile_layout}"
+tile_offset_axis = "{tile_offset_axis}"
+{padding}# How the atlas is sized: "square", "tight", {{ columns = 8 }} or {{ max_width = 256 }}.
+# packing = "tight"
+# power_of_two = true
+# Terrain tiles that don't fit in this size go into additional atlas sources.
+# max_texture_size = 4096
+
+[godot]
+project_path = {project_path}
+tile_set_path = {tile_set_path}
+
+# Tiles are loaded from 'tiles/<name>.png' and placed at fixed atlas positions.
+"#,
+        project_path = toml_value(&*config.godot.project_path),
+        tile_set_path = toml_value(&*config.godot.tile_set_path),
+    )?;
+
+    if config.tiles.is_empty() {
+        write!(
+            file,
+            r#"# [[tiles]]
+# name = "Wall"
+# position = [0, 0]
+# Tiles that are larger than one cell cover 'size' cells, starting at 'position'.
+# size = [1, 1]
+# collision = [{{ layer = 0, polygons = "auto" }}]
+# navigation = [{{ layer = 0, area = "transparent" }}]
+# custom_data = {{ cost = 1 }}
+# Animated tiles have their frames side by side, or stacked, in the image.
+# animation = {{ frames = [0.2, 0.2], speed = 1.0 }}
+# Alternatives reuse the tile's atlas cell, with flips or a tint.
+# alternatives = [{{ flip_h = true }}, {{ modulate = [1.0, 0.8, 0.6] }}]
+# Rendering properties, for example for tall tiles.
+# texture_origin = [0, 8]
+# z_index = 1
+# y_sort_origin = 8
+# material = "res://materials/wind.tres"
+"#
+        )?;
+    }
+
+    for (index, tile) in config.tiles.iter().enumerate() {
+        if index > 0 {
+            writeln!(file)?;
+        }
+
+        let [x, y] = tile.position;
+        write!(
+            file,
+            "[[tiles]]\nname = {name}\nposition = [{x}, {y}]\n",
+            name = toml_value(&*tile.name)
+        )?;
+
+        if tile.size != [1, 1] {
+            let [width, height] = tile.size;
+            writeln!(file, "size = [{width}, {height}]")?;
+        }
+
+        if let Some(animation) = &tile.animation {
+            let frames = animation
+                .frames
+                .iter()
+                .map(|&duration| toml_value(duration).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let [separation_x, separation_y] = animation.separation;
+            writeln!(
+                file,
+                "animation = {{ frames = [{frames}], speed = {speed}, columns = {columns}, separation = [{separation_x}, {separation_y}] }}",
+                speed = toml_value(animation.speed),
+                columns = animation.columns,
+            )?;
+        }
+    }
+
+    write!(
+        file,
+        r#"
+# Terrain tiles are generated from the images in 'terrains/', by picking a
+# sector around the center of the tile for each neighbor. The sectors are
+# generated from the tile shape and can be adjusted with 'mask_angles', or
+# painted in a 'mask' image, such as a copy of 'terrains/mask.template.png'
+# for "match_sides". They are blended over 'feather' pixels.
+# Numbered variants, such as 'terrains/Grass.1.png', are picked at random using
+# 'variant_weights'.
+# [[terrain_sets]]
+# mode = "match_sides"
+# feather = 2.0
+# terrains = [{{ name = "Grass" }}, {{ name = "Water" }}]
+
+# Physics layers for the 'collision' polygons of tiles and terrains.
+# [[physics_layers]]
+# collision_layer = 1
+# collision_mask = 1
+
+# Occlusion layers, where every tile gets an occluder traced from its pixels.
+# [[occlusion_layers]]
+# light_mask = 1
+# tolerance = 1.0
+
+# Navigation layers for the 'navigation' areas of tiles and terrains, which
+# can be "cell", "transparent", or "none".
+# [[navigation_layers]]
+# layers = 1
+
+# Custom data layers for the 'custom_data' values of tiles and terrains.
+# [[custom_data_layers]]
+# name = "cost"
+# type = "int"
+"#
+    )?;
+
+    Ok(())
+}
+
+/// Converts a string or number to a TOML value, which is displayed with the
+/// quotes and escapes that TOML needs.
+fn toml_value(value: impl Into<toml::Value>) -> toml::Value {
+    value.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::write_config;
+    use crate::config::Config;
+
+    #[test]
+    fn written_strings_are_valid_toml() {
+        let config: Config = toml::from_str(
+            r#"
+[tile_set]
+tile_size = [16, 16]
+
+[godot]
+project_path = 'C:\Games\"Tiles"'
+tile_set_path = "res://tile set\u0007.tres"
+
+[[tiles]]
+name = "Smile 😀\n\ttab"
+position = [1, 2]
+animation = { frames = [0.1, 1e-7], speed = 1.5 }
+"#,
+        )
+        .expect("the config should parse");
+
+        let path =
+            std::env::temp_dir().join(format!("tilecutter-config-{}.toml", std::process::id()));
+        write_config(&path, &config).expect("the config should be written");
+        let content = fs::read_to_string(&path).expect("the config should be read");
+        fs::remove_file(&path).ok();
+
+        let written: Config = toml::from_str(&content).expect("the written config should parse");
+        assert_eq!(written.godot.project_path, r#"C:\Games\"Tiles""#);
+        assert_eq!(written.godot.tile_set_path, "res://tile set\u{7}.tres");
+        assert_eq!(written.tiles[0].name, "Smile 😀\n\ttab");
+        assert_eq!(written.tiles[0].position, [1, 2]);
+
+        let animation = written.tiles[0]
+            .animation
+            .as_ref()
+            .expect("the tile should be animated");
+        assert_eq!(animation.frames, [0.1, 1e-7]);
+        assert_eq!(animation.speed, 1.5);
+    }
+}
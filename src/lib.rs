@@ -0,0 +1,374 @@
+//! Generates Godot tile sets from tile and terrain images.
+//!
+//! ```no_run
+//! use tilecutter::Project;
+//!
+//! let project = Project::load("tiles.toml")?;
+//! let mut resource = project.load_resource()?;
+//!
+//! let atlas = project
+//!     .atlas_builder()
+//!     .previous_layouts(resource.layouts())
+//!     .build()?;
+//! atlas.apply(&mut resource);
+//!
+//! project.write(&resource, &atlas.images)?;
+//! # Ok::<(), tilecutter::Error>(())
+//! ```
+
+use std::{
+    error, fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use image::RgbaImage;
+
+use config::{AnimationConfig, Config};
+use godot::resource::{Tile, TileSetResource};
+use report::{ChangeReport, ImageChange, LayoutChanges, SourceChanges, TerrainCoverage};
+
+pub use atlas::{Atlas, AtlasBuilder};
+
+mod atlas;
+pub mod config;
+pub mod godot;
+pub mod import;
+pub mod init;
+mod polygon;
+pub mod report;
+mod terrain;
+mod tile;
+
+/// An error from one of the stages of building a tile set.
+///
+/// The variant tells which stage failed. Its payload is the opaque cause of
+/// the error, which is also its [`source`](error::Error::source), and is only
+/// meant to be displayed. The content of the cause is not a part of the API,
+/// and may change between versions.
+#[derive(Debug)]
+pub enum Error {
+    /// The config file could not be read, or has invalid content.
+    Config(anyhow::Error),
+    /// The Godot resource file could not be read, or has unexpected content.
+    Resource(anyhow::Error),
+    /// The tile images could not be loaded.
+    Tiles(anyhow::Error),
+    /// The terrain images could not be loaded.
+    Terrains(anyhow::Error),
+    /// The resource or texture files could not be written.
+    Write(anyhow::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(_) => write!(f, "could not read tile set config file"),
+            Error::Resource(_) => write!(f, "could not load Godot tile set file"),
+            Error::Tiles(_) => write!(f, "could not load tile images"),
+            Error::Terrains(_) => write!(f, "could not load terrain images"),
+            Error::Write(_) => write!(f, "could not write tile set files"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Config(error)
+            | Error::Resource(error)
+            | Error::Tiles(error)
+            | Error::Terrains(error)
+            | Error::Write(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+/// A tile set config and the paths it's relative to.
+pub struct Project {
+    pub config: Config,
+    /// The directory with the config file, and the `tiles` and `terrains`
+    /// directories.
+    pub directory: PathBuf,
+}
+
+impl Project {
+    /// Loads and checks a tile set config file.
+    pub fn load(config_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let config_path = config_path.as_ref();
+        let config = load_config(config_path).map_err(Error::Config)?;
+        let directory = config_path
+            .parent()
+            .expect("could not make a parent path for the config path")
+            .to_owned();
+
+        Self::new(config, directory)
+    }
+
+    pub fn new(config: Config, directory: PathBuf) -> Result<Self, Error> {
+        if !config.godot.tile_set_path.ends_with(".tres") {
+            return Err(Error::Config(anyhow!(
+                "expected 'tile_set_path' to be on the format 'res://Path/To/resource.tres'"
+            )));
+        }
+
+        let tile_set = &config.tile_set;
+        if tile_set
+            .margins
+            .iter()
+            .any(|&margin| margin < tile_set.extrusion)
+        {
+            return Err(Error::Config(anyhow!(
+                "expected 'margins' to be at least as large as 'extrusion'"
+            )));
+        }
+
+        if tile_set
+            .separation
+            .iter()
+            .any(|&separation| separation < 2 * tile_set.extrusion)
+        {
+            return Err(Error::Config(anyhow!(
+                "expected 'separation' to be at least twice as large as 'extrusion'"
+            )));
+        }
+
+        let collision = config.tiles.iter().flat_map(|tile| &tile.collision).chain(
+            config
+                .terrain_sets
+                .iter()
+                .flat_map(|set| &set.terrains)
+                .flat_map(|terrain| &terrain.collision),
+        );
+
+        for collision in collision {
+            if collision.layer >= config.physics_layers.len() {
+                return Err(Error::Config(anyhow!(
+                    "physics layer {} is not in 'physics_layers'",
+                    collision.layer
+                )));
+            }
+        }
+
+        let navigation = config.tiles.iter().flat_map(|tile| &tile.navigation).chain(
+            config
+                .terrain_sets
+                .iter()
+                .flat_map(|set| &set.terrains)
+                .flat_map(|terrain| &terrain.navigation),
+        );
+
+        for navigation in navigation {
+            if navigation.layer >= config.navigation_layers.len() {
+                return Err(Error::Config(anyhow!(
+                    "navigation layer {} is not in 'navigation_layers'",
+                    navigation.layer
+                )));
+            }
+        }
+
+        let has_empty_animation = |animation: &Option<AnimationConfig>| {
+            animation
+                .as_ref()
+                .is_some_and(|animation| animation.frames.is_empty())
+        };
+
+        for tile in &config.tiles {
+            if tile.size.contains(&0) {
+                return Err(Error::Config(anyhow!(
+                    "expected the size of tile {:?} to be at least one cell",
+                    tile.name
+                )));
+            }
+
+            if has_empty_animation(&tile.animation) {
+                return Err(Error::Config(anyhow!(
+                    "expected the animation of tile {:?} to have at least one frame",
+                    tile.name
+                )));
+            }
+        }
+
+        for (set_index, set) in config.terrain_sets.iter().enumerate() {
+            if has_empty_animation(&set.animation) {
+                return Err(Error::Config(anyhow!(
+                    "expected the animation of terrain set {set_index} to have at least one frame"
+                )));
+            }
+        }
+
+        let materials = config
+            .tiles
+            .iter()
+            .map(|tile| &tile.rendering)
+            .chain(
+                config
+                    .terrain_sets
+                    .iter()
+                    .flat_map(|set| &set.terrains)
+                    .map(|terrain| &terrain.rendering),
+            )
+            .filter_map(|rendering| rendering.material.as_deref());
+
+        for material in materials {
+            if !material.starts_with("res://") {
+                return Err(Error::Config(anyhow!(
+                    "expected material {material:?} to be on the format 'res://Path/To/material.tres'"
+                )));
+            }
+        }
+
+        Ok(Project { config, directory })
+    }
+
+    pub fn godot_project_path(&self) -> PathBuf {
+        self.directory.join(&self.config.godot.project_path)
+    }
+
+    pub fn resource_path(&self) -> Result<PathBuf, Error> {
+        godot_path_to_absolute(&self.godot_project_path(), &self.config.godot.tile_set_path)
+            .map_err(Error::Config)
+    }
+
+    /// The paths to the texture images of the resource's atlas sources.
+    pub fn texture_paths(&self, resource: &TileSetResource) -> Result<Vec<PathBuf>, Error> {
+        resource
+            .atlas_sources
+            .iter()
+            .map(|source| {
+                if source.texture_resource.path.is_empty() {
+                    return Err(Error::Resource(anyhow!(
+                        "expected a tile set texture to have been added in the resource file via Godot"
+                    )));
+                }
+
+                godot_path_to_absolute(&self.godot_project_path(), &source.texture_resource.path)
+                    .map_err(Error::Resource)
+            })
+            .collect()
+    }
+
+    /// Loads the current Godot tile set resource.
+    pub fn load_resource(&self) -> Result<TileSetResource, Error> {
+        let resource_path = self.resource_path()?;
+
+        let godot_file = godot::parse_file(&resource_path)
+            .with_context(|| format!("could not parse {resource_path:?} as a '*.tres' file"))
+            .map_err(Error::Resource)?;
+
+        TileSetResource::init_from_file(godot_file)
+            .context("unexpected '*.tres' file content")
+            .map_err(Error::Resource)
+    }
+
+    pub fn atlas_builder(&self) -> AtlasBuilder<'_> {
+        AtlasBuilder::new(self)
+    }
+
+    /// Finds the terrain images that are missing for some combinations of
+    /// neighbors, and the tiles that can't be generated without them.
+    pub fn terrain_coverage(&self) -> Result<TerrainCoverage, Error> {
+        terrain::check_terrain_images(&self.directory, &self.config).map_err(Error::Terrains)
+    }
+
+    /// Compares an updated resource and its atlas images with the current
+    /// files, where `old_layouts` are the replaced tiles of each atlas source.
+    pub fn change_report(
+        &self,
+        resource: &TileSetResource,
+        old_layouts: &[Vec<Tile>],
+        images: &[RgbaImage],
+    ) -> Result<ChangeReport, Error> {
+        let resource_path = self.resource_path()?;
+        let texture_paths = self.texture_paths(resource)?;
+        let layouts = resource.layouts();
+
+        let mut sources = Vec::new();
+        for index in 0..old_layouts.len().max(images.len()) {
+            let old_layout = old_layouts.get(index).map_or(&[][..], Vec::as_slice);
+            let new_layout = layouts.get(index).copied().unwrap_or_default();
+
+            let image = match (texture_paths.get(index), images.get(index)) {
+                (Some(texture_path), Some(image)) => {
+                    ImageChange::compare(load_texture(texture_path)?.as_ref(), image)
+                }
+                _ => ImageChange::Removed,
+            };
+
+            sources.push(SourceChanges {
+                layout: LayoutChanges::compare(old_layout, new_layout),
+                image,
+            });
+        }
+
+        let mut old_resource_content = Vec::new();
+        File::open(&resource_path)
+            .and_then(|mut file| file.read_to_end(&mut old_resource_content))
+            .with_context(|| format!("could not read {resource_path:?}"))
+            .map_err(Error::Resource)?;
+
+        let mut new_resource_content = Vec::new();
+        resource
+            .print(&mut new_resource_content, &self.config)
+            .map_err(Error::Write)?;
+
+        Ok(ChangeReport {
+            sources,
+            resource_changed: old_resource_content != new_resource_content,
+        })
+    }
+
+    /// Writes the resource file and the images of its atlas sources.
+    pub fn write(&self, resource: &TileSetResource, images: &[RgbaImage]) -> Result<(), Error> {
+        let resource_path = self.resource_path()?;
+        let texture_paths = self.texture_paths(resource)?;
+
+        resource
+            .print_to_file(&resource_path, &self.config)
+            .with_context(|| format!("could not write {resource_path:?}"))
+            .map_err(Error::Write)?;
+
+        for (texture_path, image) in texture_paths.iter().zip(images) {
+            image
+                .save_with_format(texture_path, image::ImageFormat::Png)
+                .with_context(|| format!("could not write {texture_path:?}"))
+                .map_err(Error::Write)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads a texture image, if it exists.
+fn load_texture(path: &Path) -> Result<Option<RgbaImage>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let image = File::open(path)
+        .with_context(|| format!("could not open {path:?}"))
+        .and_then(|image_file| {
+            image::load(BufReader::new(image_file), image::ImageFormat::Png)
+                .with_context(|| format!("could not load {path:?}"))
+        })
+        .map_err(Error::Resource)?
+        .into_rgba8();
+
+    Ok(Some(image))
+}
+
+fn load_config(path: &Path) -> anyhow::Result<Config> {
+    let mut config_content = String::new();
+    File::open(path)?.read_to_string(&mut config_content)?;
+    toml::from_str::<Config>(&config_content).context("could not parse tile set config")
+}
+
+fn godot_path_to_absolute(project_path: &Path, godot_path: &str) -> anyhow::Result<PathBuf> {
+    if !godot_path.starts_with("res://") {
+        bail!("expected a Godot path on the format 'res://Path/To/resourc'");
+    }
+
+    Ok(project_path.join(godot_path.trim_start_matches("res://")))
+}
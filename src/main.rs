@@ -1,157 +1,229 @@
-use std::{
-    fs::File,
-    io::Read,
-    path::{Path, PathBuf},
-};
-
-use anyhow::{bail, Context, Result};
-use clap::Parser;
-use config::{Config, GodotConfig};
-use godot::{resource::TileSetResource, Vector2i};
-use image::{GenericImage, RgbaImage};
-use terrain::{load_terrain_tiles, TerrainTile};
-use tile::{load_tiles, Tile};
+use std::process::ExitCode;
 
-mod config;
-mod godot;
-mod terrain;
-mod tile;
+use anyhow::{bail, Result};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use tilecutter::{
+    config::{Config, GodotConfig, TileOffsetAxis, TileSetConfig, TileShape},
+    import, init, report, Project,
+};
 
 #[derive(clap::Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    export: Option<ExportArgs>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Export a tile set. This is the default command.
+    Export(ExportArgs),
+    /// Create a new tile set config file, its directories, and a Godot tile
+    /// set resource with a texture.
+    Init(InitArgs),
+    /// Cut the atlas of an existing Godot tile set into tile images, and create
+    /// a config file that keeps their atlas positions.
+    Import(ImportArgs),
+    /// List the terrain images that are missing for some combinations of
+    /// neighbors, and the tiles that can't be generated without them. Exits
+    /// with an error code if any images are missing.
+    Check(CheckArgs),
+}
+
+#[derive(clap::Args)]
+struct ExportArgs {
+    /// The config file of the tile set.
     file: String,
+    /// Report what would change instead of writing any files. Exits with an
+    /// error code if the tile set is not up to date.
     #[arg(long, short)]
     dry_run: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(clap::Args)]
+struct InitArgs {
+    /// The config file to create.
+    file: String,
+    /// The tile width and height in pixels.
+    #[arg(long, num_args = 2, value_names = ["WIDTH", "HEIGHT"], default_values_t = [16, 16])]
+    tile_size: Vec<u32>,
+    #[arg(long, value_enum, default_value_t)]
+    tile_shape: TileShape,
+    #[arg(long, value_enum, default_value_t)]
+    tile_offset_axis: TileOffsetAxis,
+    /// The Godot project directory, relative to the config file.
+    #[arg(long, default_value = ".")]
+    project_path: String,
+    #[arg(long, default_value = "res://tile_set.tres")]
+    tile_set_path: String,
+}
 
-    if let Err(error) = try_run(args) {
-        eprintln!("could not export tile set: {error:?}");
-    }
+#[derive(clap::Args)]
+struct CheckArgs {
+    /// The config file to check.
+    file: String,
 }
 
-fn try_run(args: Args) -> Result<()> {
-    // Load and check config.
-    let config = load_config(&args.file).context("could not read tile set config file")?;
-    if !config.godot.tile_set_path.ends_with(".tres") {
-        bail!("expected 'tile_set_path' to be on the format 'res://Path/To/resource.tres'");
-    }
+#[derive(clap::Args)]
+struct ImportArgs {
+    /// The Godot tile set resource to import.
+    resource: String,
+    /// The config file to create.
+    file: String,
+    /// The Godot project directory. Defaults to the closest directory with a
+    /// 'project.godot' file.
+    #[arg(long)]
+    project_path: Option<String>,
+}
 
-    // Find paths.
-    let config_directory_path = AsRef::<Path>::as_ref(&args.file)
-        .parent()
-        .expect("could not make a parent path for the config path")
-        .to_owned();
-    let godot_project_path = config_directory_path.join(&config.godot.project_path);
-    let resource_path = godot_path_to_absolute(&godot_project_path, &config.godot.tile_set_path)?;
-
-    // Load current Godot resource file.
-    let mut resource =
-        load_godot_resource(&resource_path).context("could not load Godot tile set file")?;
-
-    // Load and generate tile sheet.
-    let tiles = load_tiles(&config_directory_path, &config)?;
-    let terrain_tiles = load_terrain_tiles(&config_directory_path, &config)?;
-    let (image, layout) = write_tile_set_image(&tiles, terrain_tiles, &config);
-
-    // Update resource data.
-    resource.tile_set_atlas_source.texture_region_size = Vector2i::from(config.tile_set.tile_size);
-    resource.tile_set_atlas_source.tiles = layout;
-    let texture_path = if resource.texture_resource.path.is_empty() {
-        bail!("expected a tile set texture to have been added in the resource file via Godot");
-    } else {
-        godot_path_to_absolute(&godot_project_path, &resource.texture_resource.path)?
-    };
+fn main() -> ExitCode {
+    let args = Args::parse();
 
-    // Write resource files.
-    resource.print_to_file(resource_path, &config)?;
-    image.save_with_format(texture_path, image::ImageFormat::Png)?;
+    // Clap already requires the config file when there's no subcommand.
+    let Some(command) = args.command.or(args.export.map(Command::Export)) else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "expected a config file or a command",
+            )
+            .exit();
+    };
 
-    Ok(())
-}
+    let (result, action) = match command {
+        Command::Export(args) => (try_run(args), "export"),
+        Command::Init(args) => (init(args).map(|()| ExitCode::SUCCESS), "create"),
+        Command::Import(args) => (import(args).map(|()| ExitCode::SUCCESS), "import"),
+        Command::Check(args) => (check(args), "check"),
+    };
 
-fn load_config(path: &str) -> Result<Config> {
-    let mut config_content = String::new();
-    File::open(path)?.read_to_string(&mut config_content)?;
-    toml::from_str::<Config>(&config_content).context("could not parse tile set config")
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("could not {action} tile set: {error:?}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn load_godot_resource(resource_path: &Path) -> Result<TileSetResource> {
-    let godot_file = godot::parse_file(&resource_path)
-        .with_context(|| format!("could not parse {resource_path:?} as a '*.tres' file"))?;
+fn init(args: InitArgs) -> Result<()> {
+    let &[tile_width, tile_height] = &*args.tile_size else {
+        bail!("expected a tile width and height");
+    };
 
-    godot::resource::TileSetResource::init_from_file(godot_file)
-        .context("unexpected '*.tres' file content")
-}
+    let config = Config {
+        tile_set: TileSetConfig {
+            tile_size: [tile_width, tile_height],
+            tile_shape: args.tile_shape,
+            tile_layout: Default::default(),
+            tile_offset_axis: args.tile_offset_axis,
+            margins: [0, 0],
+            separation: [0, 0],
+            extrusion: 0,
+            packing: Default::default(),
+            power_of_two: false,
+            max_texture_size: None,
+        },
+        godot: GodotConfig {
+            project_path: args.project_path,
+            tile_set_path: args.tile_set_path,
+        },
+        tiles: Vec::new(),
+        terrain_sets: Vec::new(),
+        physics_layers: Vec::new(),
+        occlusion_layers: Vec::new(),
+        navigation_layers: Vec::new(),
+        custom_data_layers: Vec::new(),
+    };
 
-fn godot_path_to_absolute(project_path: &Path, godot_path: &str) -> Result<PathBuf> {
-    if !godot_path.starts_with("res://") {
-        bail!("expected a Godot path on the format 'res://Path/To/resourc'");
-    }
+    init::init_project(args.file.as_ref(), &config)?;
 
-    Ok(project_path.join(godot_path.trim_start_matches("res://")))
+    Ok(())
 }
 
-fn write_tile_set_image(
-    tiles: &[Tile],
-    terrain_tiles: Vec<TerrainTile>,
-    config: &Config,
-) -> (RgbaImage, Vec<godot::resource::Tile>) {
-    let [tile_width, tile_height] = config.tile_set.tile_size;
-    let total_tiles = tiles.len() as u32 + terrain_tiles.len() as u32;
-    let mut layout = Vec::new();
-    let mut image_size = 0;
+fn import(args: ImportArgs) -> Result<()> {
+    let config = import::import_project(
+        args.resource.as_ref(),
+        args.file.as_ref(),
+        args.project_path.as_deref().map(AsRef::as_ref),
+    )?;
+    eprintln!("imported {} tiles", config.tiles.len());
 
-    for tile in tiles {
-        let [x, y] = tile.config.position;
+    Ok(())
+}
 
-        let req_width = (x + 1) * tile_width;
-        let req_height = (y + 1) * tile_height;
-        let req_size = req_width.max(req_height);
+fn check(args: CheckArgs) -> Result<ExitCode> {
+    let project = Project::load(args.file)?;
+    let coverage = project.terrain_coverage()?;
 
-        image_size = image_size.max(req_size);
+    if coverage.is_complete() {
+        println!("all terrain images are present");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        print!("{coverage}");
+        print!("{}", report::MissingTiles(&coverage));
+        Ok(ExitCode::FAILURE)
     }
+}
 
-    while (image_size / tile_width) * (image_size / tile_height) < total_tiles {
-        image_size += tile_width.max(tile_height);
+fn try_run(args: ExportArgs) -> Result<ExitCode> {
+    let project = Project::load(args.file)?;
+    let mut resource = project.load_resource()?;
+
+    let atlas = project
+        .atlas_builder()
+        .previous_layouts(resource.layouts())
+        .build()?;
+    let old_layouts = atlas.apply(&mut resource);
+
+    match &*atlas.usage {
+        [usage] => eprintln!("atlas: {usage}"),
+        usage => {
+            for (index, usage) in usage.iter().enumerate() {
+                eprintln!("atlas source {index}: {usage}");
+            }
+        }
     }
 
-    let mut image = RgbaImage::new(image_size, image_size);
-
-    for tile in tiles {
-        let [x, y] = tile.config.position;
+    if args.dry_run {
+        let report = project.change_report(&resource, &old_layouts, &atlas.images)?;
+        print!("{report}");
 
-        image
-            .copy_from(&tile.image, x * tile_width, y * tile_height)
-            .expect("there should be enough room in the image for the tiles");
-
-        layout.push(godot::resource::Tile {
-            position: Vector2i::from([x, y]),
-            terrain_set: None,
-            terrain: None,
-            terrains_peering_bit: Default::default(),
-        })
+        return Ok(if report.has_changes() {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
     }
 
-    let coordinates = (0..(image_size / tile_height))
-        .flat_map(|y| (0..(image_size / tile_width)).map(move |x| (x, y)))
-        .filter(|&(x, y)| !tiles.iter().any(|tile| tile.config.position == [x, y]));
-
-    for ((x, y), tile) in coordinates.zip(terrain_tiles) {
-        image
-            .copy_from(&tile.image, x * tile_width, y * tile_height)
-            .expect("there should be enough room in the image for the terrain tiles");
-
-        layout.push(godot::resource::Tile {
-            position: Vector2i::from([x, y]),
-            terrain_set: Some(tile.terrain.terrain_set as u32),
-            terrain: Some(tile.terrain.terrain as u32),
-            terrains_peering_bit: tile.terrains_peering_bit,
-        })
+    let layouts = resource.layouts();
+    for (index, old_layout) in old_layouts.iter().enumerate() {
+        let new_layout = layouts.get(index).copied().unwrap_or_default();
+        let removed_tiles = report::LayoutChanges::compare(old_layout, new_layout).removed;
+        if removed_tiles.is_empty() {
+            continue;
+        }
+
+        if old_layouts.len() > 1 {
+            eprintln!(
+                "removing {} atlas cells from atlas source {index}:",
+                removed_tiles.len()
+            );
+        } else {
+            eprintln!(
+                "removing {} atlas cells that are no longer generated:",
+                removed_tiles.len()
+            );
+        }
+
+        for tile in &removed_tiles {
+            eprintln!("  {}", report::TileSummary(tile));
+        }
     }
 
-    (image, layout)
+    project.write(&resource, &atlas.images)?;
+
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,642 @@
+use std::collections::{HashMap, HashSet};
+
+use image::RgbaImage;
+
+use crate::config::{PolygonsConfig, TileOffsetAxis, TileSetConfig, TileShape, DEFAULT_TOLERANCE};
+
+/// Pixels with at least this alpha value are part of the traced outlines.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Makes the polygons for a tile, with pixel coordinates relative to the
+/// center of the tile.
+pub(crate) fn make_polygons(config: &PolygonsConfig, image: &RgbaImage) -> Vec<Vec<[f64; 2]>> {
+    match config {
+        PolygonsConfig::Auto(_) => trace_polygons(image, DEFAULT_TOLERANCE),
+        PolygonsConfig::Points(polygons) => center_polygons(polygons.clone(), image),
+    }
+}
+
+/// Traces the outlines of the opaque parts of a tile, with pixel coordinates
+/// relative to the center of the tile.
+pub(crate) fn trace_polygons(image: &RgbaImage, tolerance: f64) -> Vec<Vec<[f64; 2]>> {
+    center_polygons(trace_outlines(image, tolerance), image)
+}
+
+/// The area of a traced polygon.
+pub(crate) fn polygon_area(polygon: &[[f64; 2]]) -> f64 {
+    signed_area(polygon).abs()
+}
+
+/// The outline of a tile's cell, with pixel coordinates relative to the center
+/// of the tile.
+pub(crate) fn cell_polygon(tile_set: &TileSetConfig) -> Vec<[f64; 2]> {
+    let [width, height] = tile_set.tile_size.map(f64::from);
+    let points: &[[f64; 2]] = match (tile_set.tile_shape, tile_set.tile_offset_axis) {
+        (TileShape::Square | TileShape::HalfOffsetSquare, _) => {
+            &[[-0.5, -0.5], [0.5, -0.5], [0.5, 0.5], [-0.5, 0.5]]
+        }
+        (TileShape::Isometric, _) => &[[0.0, -0.5], [0.5, 0.0], [0.0, 0.5], [-0.5, 0.0]],
+        (TileShape::Hexagon, TileOffsetAxis::Horizontal) => &[
+            [0.0, -0.5],
+            [0.5, -0.25],
+            [0.5, 0.25],
+            [0.0, 0.5],
+            [-0.5, 0.25],
+            [-0.5, -0.25],
+        ],
+        (TileShape::Hexagon, TileOffsetAxis::Vertical) => &[
+            [-0.25, -0.5],
+            [0.25, -0.5],
+            [0.5, 0.0],
+            [0.25, 0.5],
+            [-0.25, 0.5],
+            [-0.5, 0.0],
+        ],
+    };
+
+    points
+        .iter()
+        .map(|[x, y]| [x * width, y * height])
+        .collect()
+}
+
+/// Splits the transparent pixels inside a cell into convex polygons, with
+/// pixel coordinates relative to the center of the tile. Returns the vertices
+/// and the polygons as indices into them.
+///
+/// The polygons are rectangles, with extra vertices where other rectangles'
+/// corners touch their sides. That way, neighboring polygons share whole
+/// edges and are connected in Godot's navigation mesh.
+pub(crate) fn transparent_mesh(
+    image: &RgbaImage,
+    cell: &[[f64; 2]],
+) -> (Vec<[f64; 2]>, Vec<Vec<usize>>) {
+    let center_x = image.width() as f64 / 2.0;
+    let center_y = image.height() as f64 / 2.0;
+    let is_walkable = |x: u32, y: u32| {
+        let center = [x as f64 + 0.5 - center_x, y as f64 + 0.5 - center_y];
+        image.get_pixel(x, y)[3] < ALPHA_THRESHOLD && is_inside_convex(center, cell)
+    };
+
+    // Rectangles are made from runs of walkable pixels, which are merged with
+    // identical runs in the row below.
+    let mut rectangles = Vec::new();
+    let mut open = Vec::<[i64; 4]>::new();
+    for y in 0..image.height() {
+        let mut runs = Vec::new();
+        let mut x = 0;
+        while x < image.width() {
+            if !is_walkable(x, y) {
+                x += 1;
+                continue;
+            }
+
+            let start = x;
+            while x < image.width() && is_walkable(x, y) {
+                x += 1;
+            }
+            runs.push([start as i64, x as i64]);
+        }
+
+        let y = y as i64;
+        let mut next_open = Vec::new();
+        for [left, right] in runs {
+            match open
+                .iter()
+                .position(|&[x0, _, x1, _]| x0 == left && x1 == right)
+            {
+                Some(index) => {
+                    let mut rectangle = open.swap_remove(index);
+                    rectangle[3] = y + 1;
+                    next_open.push(rectangle);
+                }
+                None => next_open.push([left, y, right, y + 1]),
+            }
+        }
+
+        rectangles.append(&mut open);
+        open = next_open;
+    }
+    rectangles.append(&mut open);
+
+    let corners = rectangles
+        .iter()
+        .flat_map(|&[x0, y0, x1, y1]| [[x0, y0], [x1, y0], [x1, y1], [x0, y1]])
+        .collect::<HashSet<_>>();
+
+    let mut vertex_indices = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut polygons = Vec::new();
+
+    for &[x0, y0, x1, y1] in &rectangles {
+        let sides = [
+            ([x0, y0], [x1, y0]),
+            ([x1, y0], [x1, y1]),
+            ([x1, y1], [x0, y1]),
+            ([x0, y1], [x0, y0]),
+        ];
+
+        let mut points = Vec::new();
+        for ([start_x, start_y], [end_x, end_y]) in sides {
+            let mut side_corners = corners
+                .iter()
+                .copied()
+                .filter(|&[x, y]| {
+                    let is_between = |value: i64, start: i64, end: i64| {
+                        value > start.min(end) && value < start.max(end)
+                    };
+
+                    (x == start_x && x == end_x && is_between(y, start_y, end_y))
+                        || (y == start_y && y == end_y && is_between(x, start_x, end_x))
+                })
+                .collect::<Vec<_>>();
+            side_corners.sort_by_key(|&[x, y]| (x - start_x).abs() + (y - start_y).abs());
+
+            points.push([start_x, start_y]);
+            points.extend(side_corners);
+        }
+
+        let polygon = points
+            .into_iter()
+            .map(|point| {
+                *vertex_indices.entry(point).or_insert_with(|| {
+                    let [x, y] = point;
+                    vertices.push([x as f64 - center_x, y as f64 - center_y]);
+                    vertices.len() - 1
+                })
+            })
+            .collect();
+        polygons.push(polygon);
+    }
+
+    (vertices, polygons)
+}
+
+/// Checks if a point is inside, or on the edge of, a convex polygon with any
+/// winding.
+fn is_inside_convex([x, y]: [f64; 2], polygon: &[[f64; 2]]) -> bool {
+    // The point is on the same side of every edge as the rest of the polygon.
+    let winding = signed_area(polygon).signum();
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .all(|(&[x1, y1], &[x2, y2])| {
+            ((x2 - x1) * (y - y1) - (y2 - y1) * (x - x1)) * winding >= 0.0
+        })
+}
+
+fn center_polygons(polygons: Vec<Vec<[f64; 2]>>, image: &RgbaImage) -> Vec<Vec<[f64; 2]>> {
+    let center_x = image.width() as f64 / 2.0;
+    let center_y = image.height() as f64 / 2.0;
+
+    polygons
+        .into_iter()
+        .map(|polygon| {
+            polygon
+                .into_iter()
+                .map(|[x, y]| [x - center_x, y - center_y])
+                .collect()
+        })
+        .collect()
+}
+
+/// Traces the outlines of the opaque parts of an image, as simplified polygons
+/// with the corners of the pixels as coordinates. Holes are not included.
+fn trace_outlines(image: &RgbaImage, tolerance: f64) -> Vec<Vec<[f64; 2]>> {
+    let width = image.width() as i64;
+    let height = image.height() as i64;
+    let is_opaque = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < width
+            && y < height
+            && image.get_pixel(x as u32, y as u32)[3] >= ALPHA_THRESHOLD
+    };
+
+    // Edges between opaque and transparent pixels, going clockwise around the
+    // opaque pixels.
+    let mut edges = HashMap::<[i64; 2], Vec<[i64; 2]>>::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !is_opaque(x, y) {
+                continue;
+            }
+
+            let sides = [
+                ((0, -1), [x, y], [x + 1, y]),
+                ((1, 0), [x + 1, y], [x + 1, y + 1]),
+                ((0, 1), [x + 1, y + 1], [x, y + 1]),
+                ((-1, 0), [x, y + 1], [x, y]),
+            ];
+
+            for ((dx, dy), start, end) in sides {
+                if !is_opaque(x + dx, y + dy) {
+                    edges.entry(start).or_default().push(end);
+                }
+            }
+        }
+    }
+
+    let mut starts = edges.keys().copied().collect::<Vec<_>>();
+    starts.sort_by_key(|&[x, y]| (y, x));
+
+    let mut polygons = Vec::new();
+    for start in starts {
+        while let Some(outline) = trace_outline(&mut edges, start) {
+            // Outer outlines are clockwise and holes are counter-clockwise.
+            if signed_area(&outline) <= 0.0 {
+                continue;
+            }
+
+            let polygon = simplify(&remove_collinear(&outline), tolerance);
+            if polygon.len() >= 3 {
+                polygons.push(polygon);
+            }
+        }
+    }
+
+    polygons
+}
+
+/// Follows and removes edges until it gets back to `start`. Turns right where
+/// two outlines meet, so that diagonally touching pixels become separate
+/// polygons.
+fn trace_outline(
+    edges: &mut HashMap<[i64; 2], Vec<[i64; 2]>>,
+    start: [i64; 2],
+) -> Option<Vec<[f64; 2]>> {
+    let mut outline = Vec::new();
+    let mut current = start;
+    let mut direction: Option<[i64; 2]> = None;
+
+    loop {
+        let ends = edges.get_mut(&current)?;
+
+        let index = match direction {
+            Some([dx, dy]) if ends.len() > 1 => ends
+                .iter()
+                .position(|&[x, y]| [x - current[0], y - current[1]] == [-dy, dx])
+                .unwrap_or(0),
+            _ => 0,
+        };
+
+        let next = ends.swap_remove(index);
+        if ends.is_empty() {
+            edges.remove(&current);
+        }
+
+        outline.push([current[0] as f64, current[1] as f64]);
+        direction = Some([next[0] - current[0], next[1] - current[1]]);
+        current = next;
+
+        if current == start {
+            return Some(outline);
+        }
+    }
+}
+
+fn signed_area(polygon: &[[f64; 2]]) -> f64 {
+    let sum: f64 = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|([x1, y1], [x2, y2])| x1 * y2 - x2 * y1)
+        .sum();
+
+    sum / 2.0
+}
+
+fn remove_collinear(polygon: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let count = polygon.len();
+
+    (0..count)
+        .filter(|&index| {
+            let [x0, y0] = polygon[(index + count - 1) % count];
+            let [x1, y1] = polygon[index];
+            let [x2, y2] = polygon[(index + 1) % count];
+
+            (x1 - x0) * (y2 - y1) != (y1 - y0) * (x2 - x1)
+        })
+        .map(|index| polygon[index])
+        .collect()
+}
+
+/// Simplifies a closed polygon with the Ramer-Douglas-Peucker algorithm, by
+/// splitting it at the point that is furthest from the first point.
+fn simplify(polygon: &[[f64; 2]], tolerance: f64) -> Vec<[f64; 2]> {
+    let Some(&first) = polygon.first() else {
+        return Vec::new();
+    };
+
+    let Some(split) = (1..polygon.len())
+        .max_by(|&a, &b| distance(first, polygon[a]).total_cmp(&distance(first, polygon[b])))
+    else {
+        return polygon.to_vec();
+    };
+
+    let mut result = Vec::new();
+    simplify_line(&polygon[..=split], tolerance, &mut result);
+    result.pop();
+
+    let mut second_half = polygon[split..].to_vec();
+    second_half.push(first);
+    simplify_line(&second_half, tolerance, &mut result);
+    result.pop();
+
+    result
+}
+
+/// Simplifies an open line, and adds all of its points to `result`.
+fn simplify_line(line: &[[f64; 2]], tolerance: f64, result: &mut Vec<[f64; 2]>) {
+    let (&first, &last) = match line {
+        [first, .., last] => (first, last),
+        _ => {
+            result.extend_from_slice(line);
+            return;
+        }
+    };
+
+    let furthest = (1..line.len() - 1)
+        .map(|index| (index, line_distance(line[index], first, last)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    match furthest {
+        Some((index, distance)) if distance > tolerance => {
+            simplify_line(&line[..=index], tolerance, result);
+            result.pop();
+            simplify_line(&line[index..], tolerance, result);
+        }
+        _ => result.extend([first, last]),
+    }
+}
+
+fn distance([x1, y1]: [f64; 2], [x2, y2]: [f64; 2]) -> f64 {
+    (x2 - x1).hypot(y2 - y1)
+}
+
+/// The distance from `point` to the line through `start` and `end`.
+fn line_distance(point: [f64; 2], start: [f64; 2], end: [f64; 2]) -> f64 {
+    let length = distance(start, end);
+    if length == 0.0 {
+        return distance(point, start);
+    }
+
+    let [x, y] = point;
+    let [x1, y1] = start;
+    let [x2, y2] = end;
+
+    ((x2 - x1) * (y1 - y) - (x1 - x) * (y2 - y1)).abs() / length
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::{
+        cell_polygon, is_inside_convex, simplify, trace_outline, trace_outlines, transparent_mesh,
+    };
+    use crate::config::TileSetConfig;
+
+    /// Makes an image from rows of `#` for opaque and `.` for transparent
+    /// pixels.
+    fn image(rows: &[&str]) -> RgbaImage {
+        RgbaImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'#' => Rgba([255, 255, 255, 255]),
+                _ => Rgba([0, 0, 0, 0]),
+            }
+        })
+    }
+
+    #[test]
+    fn square_outline() {
+        let polygons = trace_outlines(&image(&["####", "####", "####", "####"]), 0.5);
+
+        assert_eq!(polygons, [[[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]]]);
+    }
+
+    #[test]
+    fn l_shaped_outline() {
+        let polygons = trace_outlines(&image(&["##..", "##..", "####", "####"]), 0.5);
+
+        assert_eq!(
+            polygons,
+            [[
+                [0.0, 0.0],
+                [2.0, 0.0],
+                [2.0, 2.0],
+                [4.0, 2.0],
+                [4.0, 4.0],
+                [0.0, 4.0]
+            ]]
+        );
+    }
+
+    #[test]
+    fn diagonal_pixels_are_separate() {
+        let polygons = trace_outlines(&image(&["#.", ".#"]), 0.5);
+
+        assert_eq!(
+            polygons,
+            [
+                [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+                [[1.0, 1.0], [2.0, 1.0], [2.0, 2.0], [1.0, 2.0]]
+            ]
+        );
+    }
+
+    #[test]
+    fn holes_are_skipped() {
+        let polygons = trace_outlines(&image(&["###", "#.#", "###"]), 0.5);
+
+        assert_eq!(polygons, [[[0.0, 0.0], [3.0, 0.0], [3.0, 3.0], [0.0, 3.0]]]);
+    }
+
+    #[test]
+    fn outline_removes_its_edges() {
+        let mut edges = HashMap::from([
+            ([0, 0], vec![[1, 0]]),
+            ([1, 0], vec![[1, 1]]),
+            ([1, 1], vec![[0, 1]]),
+            ([0, 1], vec![[0, 0]]),
+        ]);
+
+        let outline = trace_outline(&mut edges, [0, 0]);
+
+        assert_eq!(
+            outline,
+            Some(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]])
+        );
+        assert!(edges.is_empty());
+        assert_eq!(trace_outline(&mut edges, [0, 0]), None);
+    }
+
+    #[test]
+    fn staircases_are_simplified() {
+        let polygon = [
+            [0.0, 0.0],
+            [4.0, 0.0],
+            [4.0, 4.0],
+            [3.0, 4.0],
+            [3.0, 3.0],
+            [2.0, 3.0],
+            [2.0, 2.0],
+            [1.0, 2.0],
+            [1.0, 1.0],
+            [0.0, 1.0],
+        ];
+
+        // The steps are less than a pixel from the diagonal, but more than a
+        // quarter pixel from any line between the other points.
+        assert_eq!(
+            simplify(&polygon, 1.0),
+            [[0.0, 0.0], [4.0, 0.0], [4.0, 4.0]]
+        );
+        assert_eq!(simplify(&polygon, 0.25), polygon);
+    }
+
+    fn tile_set(properties: &str) -> TileSetConfig {
+        toml::from_str(&format!("tile_size = [16, 16]\n{properties}"))
+            .expect("the tile set config should parse")
+    }
+
+    /// Makes the mesh and returns each polygon's points.
+    fn mesh_polygons(image: &RgbaImage, cell: &[[f64; 2]]) -> Vec<Vec<[f64; 2]>> {
+        let (vertices, polygons) = transparent_mesh(image, cell);
+        polygons
+            .into_iter()
+            .map(|polygon| polygon.into_iter().map(|index| vertices[index]).collect())
+            .collect()
+    }
+
+    /// Checks if a point is strictly inside a mesh rectangle.
+    fn is_inside_rectangle([x, y]: [f64; 2], polygon: &[[f64; 2]]) -> bool {
+        let (min, max) = polygon.iter().fold(
+            ([f64::MAX; 2], [f64::MIN; 2]),
+            |([min_x, min_y], [max_x, max_y]), &[x, y]| {
+                ([min_x.min(x), min_y.min(y)], [max_x.max(x), max_y.max(y)])
+            },
+        );
+
+        x > min[0] && x < max[0] && y > min[1] && y < max[1]
+    }
+
+    /// Checks that every edge between two polygons is an edge of both, so
+    /// that the polygons are connected.
+    fn assert_shared_edges(polygons: &[Vec<[f64; 2]>]) {
+        let edges = polygons
+            .iter()
+            .flat_map(|polygon| polygon.iter().zip(polygon.iter().cycle().skip(1)))
+            .collect::<Vec<_>>();
+
+        for &(&[x1, y1], &[x2, y2]) in &edges {
+            // A point just outside of the edge, for clockwise polygons.
+            let length = (x2 - x1).hypot(y2 - y1);
+            let outside = [
+                (x1 + x2) / 2.0 + (y2 - y1) / length * 0.25,
+                (y1 + y2) / 2.0 - (x2 - x1) / length * 0.25,
+            ];
+
+            if polygons
+                .iter()
+                .any(|polygon| is_inside_rectangle(outside, polygon))
+            {
+                assert!(
+                    edges.contains(&(&[x2, y2], &[x1, y1])),
+                    "the edge from {:?} to {:?} is only partly shared",
+                    [x1, y1],
+                    [x2, y2]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_fill_the_cell() {
+        let image = image(&["................"; 16]);
+
+        for properties in [
+            "tile_shape = \"square\"",
+            "tile_shape = \"isometric\"",
+            "tile_shape = \"half_offset_square\"",
+            "tile_shape = \"hexagon\"\ntile_offset_axis = \"horizontal\"",
+            "tile_shape = \"hexagon\"\ntile_offset_axis = \"vertical\"",
+        ] {
+            let cell = cell_polygon(&tile_set(properties));
+            let polygons = mesh_polygons(&image, &cell);
+
+            // Every pixel with its center inside the cell is covered by
+            // exactly one polygon, and the others aren't covered.
+            for (x, y) in (0..16).flat_map(|y| (0..16).map(move |x| (x, y))) {
+                let center = [x as f64 - 7.5, y as f64 - 7.5];
+                let coverage = polygons
+                    .iter()
+                    .filter(|polygon| is_inside_rectangle(center, polygon))
+                    .count();
+                let expected = usize::from(is_inside_convex(center, &cell));
+                assert_eq!(coverage, expected, "pixel ({x}, {y}) with {properties}");
+            }
+
+            assert_shared_edges(&polygons);
+        }
+
+        let square = mesh_polygons(&image, &cell_polygon(&tile_set("tile_shape = \"square\"")));
+        assert_eq!(
+            square,
+            [[[-8.0, -8.0], [8.0, -8.0], [8.0, 8.0], [-8.0, 8.0]]]
+        );
+    }
+
+    #[test]
+    fn meshes_go_around_obstacles() {
+        let image = image(&[
+            "........", "........", "........", "...##...", "...##...", "........", "........",
+            "........",
+        ]);
+        let cell = cell_polygon(&TileSetConfig {
+            tile_size: [8, 8],
+            ..tile_set("tile_shape = \"square\"")
+        });
+
+        let polygons = mesh_polygons(&image, &cell);
+
+        assert_eq!(
+            polygons,
+            [
+                vec![
+                    [-4.0, -4.0],
+                    [4.0, -4.0],
+                    [4.0, -1.0],
+                    [1.0, -1.0],
+                    [-1.0, -1.0],
+                    [-4.0, -1.0]
+                ],
+                vec![[-4.0, -1.0], [-1.0, -1.0], [-1.0, 1.0], [-4.0, 1.0]],
+                vec![[1.0, -1.0], [4.0, -1.0], [4.0, 1.0], [1.0, 1.0]],
+                vec![
+                    [-4.0, 1.0],
+                    [-1.0, 1.0],
+                    [1.0, 1.0],
+                    [4.0, 1.0],
+                    [4.0, 4.0],
+                    [-4.0, 4.0]
+                ],
+            ]
+        );
+        assert_shared_edges(&polygons);
+    }
+
+    #[test]
+    fn convex_polygons_have_any_winding() {
+        let clockwise = [[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]];
+        let counter_clockwise = [[0.0, 0.0], [0.0, 2.0], [2.0, 2.0], [2.0, 0.0]];
+
+        for polygon in [clockwise, counter_clockwise] {
+            assert!(is_inside_convex([1.0, 1.0], &polygon));
+            assert!(is_inside_convex([2.0, 1.0], &polygon));
+            assert!(!is_inside_convex([3.0, 1.0], &polygon));
+            assert!(!is_inside_convex([1.0, -0.5], &polygon));
+        }
+    }
+}
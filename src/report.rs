@@ -0,0 +1,484 @@
+use std::{collections::HashMap, fmt};
+
+use image::RgbaImage;
+
+use crate::godot::{resource::Tile, Vector2i};
+
+/// A summary of what an export would change in the tile set files.
+pub struct ChangeReport {
+    /// The changes in each atlas source, including the ones that would be
+    /// removed.
+    pub sources: Vec<SourceChanges>,
+    pub resource_changed: bool,
+}
+
+/// What an export would change in an atlas source and its texture. Tiles that
+/// move to another atlas source count as removed from one and added to the
+/// other.
+pub struct SourceChanges {
+    pub layout: LayoutChanges,
+    pub image: ImageChange,
+}
+
+impl ChangeReport {
+    pub fn has_changes(&self) -> bool {
+        self.sources
+            .iter()
+            .any(|source| source.layout.has_changes() || source.image != ImageChange::Unchanged)
+            || self.resource_changed
+    }
+}
+
+impl fmt::Display for ChangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, source) in self.sources.iter().enumerate() {
+            if self.sources.len() > 1 {
+                writeln!(f, "atlas source {index}:")?;
+            }
+
+            write!(f, "{}", source.layout)?;
+
+            match source.image {
+                ImageChange::Unchanged => writeln!(f, "texture image: unchanged")?,
+                ImageChange::Missing => writeln!(f, "texture image: would be created")?,
+                ImageChange::Removed => writeln!(f, "texture image: would no longer be used")?,
+                ImageChange::Resized { old, new } => writeln!(
+                    f,
+                    "texture image: would be resized from {}x{} to {}x{}",
+                    old[0], old[1], new[0], new[1]
+                )?,
+                ImageChange::Pixels(count) => {
+                    writeln!(f, "texture image: {count} pixels would change")?
+                }
+            }
+        }
+
+        if self.resource_changed {
+            writeln!(f, "resource file: would change")
+        } else {
+            writeln!(f, "resource file: unchanged")
+        }
+    }
+}
+
+/// How much of the atlas image the tiles cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasUsage {
+    /// The width and height of the image, in pixels.
+    pub image_size: [u32; 2],
+    /// The number of columns and rows of cells in the image.
+    pub grid_size: [u32; 2],
+    pub used_cells: u32,
+}
+
+impl fmt::Display for AtlasUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [width, height] = self.image_size;
+        let [columns, rows] = self.grid_size;
+        let cell_count = columns * rows;
+        let percentage = if cell_count == 0 {
+            0.0
+        } else {
+            100.0 * f64::from(self.used_cells) / f64::from(cell_count)
+        };
+
+        write!(
+            f,
+            "{width}x{height} pixels, {} of {cell_count} cells used ({percentage:.0}%)",
+            self.used_cells
+        )
+    }
+}
+
+/// The terrain images that each terrain set would need to have tiles for
+/// every combination of neighbors, but doesn't have.
+pub struct TerrainCoverage {
+    pub sets: Vec<TerrainSetCoverage>,
+}
+
+/// The missing images of a terrain set, and the tiles that can't be generated
+/// without them.
+pub struct TerrainSetCoverage {
+    pub missing_images: Vec<MissingImage>,
+    pub missing_tiles: Vec<MissingTile>,
+    /// The number of tiles that the set would have with all images.
+    pub tile_count: usize,
+}
+
+impl TerrainCoverage {
+    pub fn is_complete(&self) -> bool {
+        self.sets
+            .iter()
+            .all(|set| set.missing_images.is_empty() && set.missing_tiles.is_empty())
+    }
+}
+
+impl fmt::Display for TerrainCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, set) in self.sets.iter().enumerate() {
+            if !set.missing_images.is_empty() {
+                writeln!(f, "terrain set {index} is missing these images:")?;
+                for image in &set.missing_images {
+                    writeln!(f, "  {image}")?;
+                }
+            }
+
+            if !set.missing_tiles.is_empty() {
+                writeln!(
+                    f,
+                    "{} of the {} tiles in terrain set {index} can't be generated",
+                    set.missing_tiles.len(),
+                    set.tile_count
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays the tiles that can't be generated in each terrain set.
+pub struct MissingTiles<'a>(pub &'a TerrainCoverage);
+
+impl fmt::Display for MissingTiles<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, set) in self.0.sets.iter().enumerate() {
+            if set.missing_tiles.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "tiles that can't be generated in terrain set {index}:")?;
+            for tile in &set.missing_tiles {
+                writeln!(f, "  {tile}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A combination of peering bits that can't be painted, because there's no
+/// tile for it.
+pub struct MissingTile {
+    /// The name of the center terrain.
+    pub terrain: String,
+    /// The names of the neighbors that have a terrain, and their terrains.
+    pub neighbors: Vec<(&'static str, String)>,
+}
+
+impl fmt::Display for MissingTile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.terrain)?;
+
+        for (index, (neighbor, terrain)) in self.neighbors.iter().enumerate() {
+            let separator = if index == 0 { " with" } else { "," };
+            write!(f, "{separator} {neighbor}: {terrain}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A terrain image that is missing, which is displayed with its file name and
+/// the tiles that can't be generated without it.
+pub struct MissingImage {
+    /// The names of the center terrain, and the one or two other terrains.
+    pub terrains: Vec<String>,
+}
+
+impl fmt::Display for MissingImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.terrains {
+            [center] => write!(f, "{center}.png: no tiles with {center} in the center"),
+            [center, other] => write!(
+                f,
+                "{center}-{other}.png: no {center} tiles with {other} on any neighbor"
+            ),
+            [center, other1, other2] => write!(
+                f,
+                "{center}-{other1}-{other2}.png or {center}-{other2}-{other1}.png: no {center} tiles with {other1} and {other2} on adjacent neighbors"
+            ),
+            terrains => write!(f, "{}.png", terrains.join("-")),
+        }
+    }
+}
+
+/// The difference between the atlas tiles in the current resource file and
+/// the newly generated ones.
+#[derive(Default)]
+pub struct LayoutChanges {
+    pub added: Vec<Tile>,
+    pub moved: Vec<(Vector2i, Tile)>,
+    pub removed: Vec<Tile>,
+    pub changed: Vec<(Tile, Tile)>,
+}
+
+impl LayoutChanges {
+    /// Compares two layouts. Tiles are primarily matched by position and
+    /// terrain data. Terrain tiles with the same terrain data in different
+    /// positions count as moved, and tiles in the same position with different
+    /// terrain data count as changed.
+    pub fn compare(old: &[Tile], new: &[Tile]) -> Self {
+        let mut changes = Self::default();
+
+        let old_by_position: HashMap<_, _> = old.iter().map(|tile| (tile.position, tile)).collect();
+        let new_by_position: HashMap<_, _> = new.iter().map(|tile| (tile.position, tile)).collect();
+
+        let is_unchanged = |tile: &Tile, others: &HashMap<Vector2i, &Tile>| {
+            others
+                .get(&tile.position)
+                .is_some_and(|other| other.terrain_key() == tile.terrain_key())
+        };
+
+        let mut old_remaining: Vec<_> = old
+            .iter()
+            .filter(|tile| !is_unchanged(tile, &new_by_position))
+            .collect();
+        let mut new_remaining: Vec<_> = new
+            .iter()
+            .filter(|tile| !is_unchanged(tile, &old_by_position))
+            .collect();
+
+        // Terrain tiles are identified by their terrain data, so the same data
+        // in another cell means that the tile moved.
+        let mut old_by_key = HashMap::<_, Vec<_>>::new();
+        for (index, tile) in old_remaining.iter().enumerate() {
+            if tile.terrain_set.is_some() {
+                old_by_key
+                    .entry(tile.terrain_key())
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let mut moved_old = vec![false; old_remaining.len()];
+        new_remaining.retain(|tile| {
+            let old_index = old_by_key
+                .get_mut(&tile.terrain_key())
+                .and_then(|indices| indices.pop());
+
+            if let Some(old_index) = old_index {
+                moved_old[old_index] = true;
+                changes
+                    .moved
+                    .push((old_remaining[old_index].position, (*tile).clone()));
+                false
+            } else {
+                true
+            }
+        });
+
+        let mut moved_old = moved_old.into_iter();
+        old_remaining.retain(|_| !moved_old.next().unwrap_or_default());
+
+        let mut old_remaining_by_position: HashMap<_, _> = old_remaining
+            .into_iter()
+            .map(|tile| (tile.position, tile))
+            .collect();
+
+        for tile in new_remaining {
+            if let Some(old_tile) = old_remaining_by_position.remove(&tile.position) {
+                changes.changed.push((old_tile.clone(), tile.clone()));
+            } else {
+                changes.added.push(tile.clone());
+            }
+        }
+
+        changes.removed = old_remaining_by_position.into_values().cloned().collect();
+
+        let key = |position: Vector2i| (position.y, position.x);
+        changes.added.sort_by_key(|tile| key(tile.position));
+        changes.moved.sort_by_key(|(_, tile)| key(tile.position));
+        changes.removed.sort_by_key(|tile| key(tile.position));
+        changes.changed.sort_by_key(|(_, tile)| key(tile.position));
+
+        changes
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !(self.added.is_empty()
+            && self.moved.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty())
+    }
+}
+
+impl fmt::Display for LayoutChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.has_changes() {
+            return writeln!(f, "atlas cells: unchanged");
+        }
+
+        if !self.added.is_empty() {
+            writeln!(f, "added {} atlas cells:", self.added.len())?;
+            for tile in &self.added {
+                writeln!(f, "  {}", TileSummary(tile))?;
+            }
+        }
+
+        if !self.moved.is_empty() {
+            writeln!(f, "moved {} atlas cells:", self.moved.len())?;
+            for (old_position, tile) in &self.moved {
+                writeln!(
+                    f,
+                    "  {} -> {} {}",
+                    Position(*old_position),
+                    Position(tile.position),
+                    TileData(tile)
+                )?;
+            }
+        }
+
+        if !self.removed.is_empty() {
+            writeln!(f, "removed {} atlas cells:", self.removed.len())?;
+            for tile in &self.removed {
+                writeln!(f, "  {}", TileSummary(tile))?;
+            }
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(
+                f,
+                "changed peering bits in {} atlas cells:",
+                self.changed.len()
+            )?;
+            for (old_tile, new_tile) in &self.changed {
+                write!(f, "  {}", Position(new_tile.position))?;
+
+                if old_tile.terrain_set != new_tile.terrain_set
+                    || old_tile.terrain != new_tile.terrain
+                {
+                    write!(
+                        f,
+                        " terrain {} -> {}",
+                        Terrain(old_tile.terrain_set, old_tile.terrain),
+                        Terrain(new_tile.terrain_set, new_tile.terrain)
+                    )?;
+                }
+
+                for ((neighbor, old_bit), (_, new_bit)) in old_tile
+                    .terrains_peering_bit
+                    .iter()
+                    .zip(new_tile.terrains_peering_bit.iter())
+                {
+                    if old_bit != new_bit {
+                        write!(
+                            f,
+                            " {}: {} -> {}",
+                            neighbor.name(),
+                            Bit(old_bit),
+                            Bit(new_bit)
+                        )?;
+                    }
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How the generated texture image differs from the current one.
+#[derive(PartialEq, Eq, Debug)]
+pub enum ImageChange {
+    Unchanged,
+    Missing,
+    /// The atlas source would be removed, and its image is no longer used.
+    Removed,
+    Resized {
+        old: [u32; 2],
+        new: [u32; 2],
+    },
+    Pixels(usize),
+}
+
+impl ImageChange {
+    pub fn compare(old: Option<&RgbaImage>, new: &RgbaImage) -> Self {
+        let Some(old) = old else {
+            return Self::Missing;
+        };
+
+        if old.dimensions() != new.dimensions() {
+            return Self::Resized {
+                old: [old.width(), old.height()],
+                new: [new.width(), new.height()],
+            };
+        }
+
+        let count = old
+            .pixels()
+            .zip(new.pixels())
+            .filter(|(old, new)| old != new)
+            .count();
+
+        if count == 0 {
+            Self::Unchanged
+        } else {
+            Self::Pixels(count)
+        }
+    }
+}
+
+struct Position(Vector2i);
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.0.x, self.0.y)
+    }
+}
+
+struct Terrain(Option<u32>, Option<u32>);
+
+impl fmt::Display for Terrain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terrain(Some(set), Some(terrain)) => write!(f, "{set}/{terrain}"),
+            Terrain(Some(set), None) => write!(f, "{set}/none"),
+            Terrain(None, _) => write!(f, "none"),
+        }
+    }
+}
+
+struct Bit(Option<u32>);
+
+impl fmt::Display for Bit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(terrain) => write!(f, "{terrain}"),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// Displays a tile's position and terrain data.
+pub struct TileSummary<'a>(pub &'a Tile);
+
+impl fmt::Display for TileSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", Position(self.0.position), TileData(self.0))
+    }
+}
+
+struct TileData<'a>(&'a Tile);
+
+impl fmt::Display for TileData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tile = self.0;
+
+        if tile.terrain_set.is_none() {
+            return write!(f, "tile");
+        }
+
+        write!(f, "terrain {}", Terrain(tile.terrain_set, tile.terrain))?;
+
+        for (neighbor, bit) in tile.terrains_peering_bit.iter() {
+            if bit.is_some() {
+                write!(f, " {}: {}", neighbor.name(), Bit(bit))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,72 +1,509 @@
 use core::str;
-use std::{fs::File, io::BufReader, os::unix::ffi::OsStrExt, path::Path};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    f64::consts::TAU,
+    fs::File,
+    io::BufReader,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
 use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
 use itertools::Itertools;
 
 use crate::{
-    config::{Config, TerrainSetConfig},
-    godot::resource::PeeringBit,
+    config::{AlternativeConfig, Config, TerrainMode, TerrainSetConfig, TileOffsetAxis, TileShape},
+    godot::resource::{CellNeighbor, PeeringBit},
+    report::{MissingImage, MissingTile, TerrainCoverage, TerrainSetCoverage},
 };
 
-const MASK_COLORS: [Rgba<u8>; 6] = [
+/// The colors of the mask image sectors. Sector `i` is the part of the tile
+/// between neighbor `i` and neighbor `i + 1` from `terrain_neighbors`.
+const MASK_COLORS: [Rgba<u8>; 12] = [
     Rgba([255, 0, 0, 255]),
     Rgba([0, 255, 0, 255]),
     Rgba([0, 0, 255, 255]),
     Rgba([0, 255, 255, 255]),
     Rgba([255, 0, 255, 255]),
     Rgba([255, 255, 0, 255]),
+    Rgba([255, 128, 0, 255]),
+    Rgba([128, 0, 255, 255]),
+    Rgba([0, 128, 255, 255]),
+    Rgba([255, 0, 128, 255]),
+    Rgba([128, 255, 0, 255]),
+    Rgba([0, 255, 128, 255]),
 ];
 
+/// The mask image that `init` writes, to be painted over and configured as a
+/// terrain set's `mask`.
+pub(crate) const MASK_TEMPLATE_NAME: &str = "mask.template.png";
+
 pub(crate) fn load_terrain_tiles(config_path: &Path, config: &Config) -> Result<Vec<TerrainTile>> {
     if config.terrain_sets.is_empty() {
         return Ok(Vec::new());
     }
 
     let directory_path = config_path.join("terrains");
+    let images = load_images(&directory_path, config)?;
+    let mut mask_images = HashMap::new();
+
+    // Missing images only leave out the tiles that need them, and a partial
+    // set is still usable, so the export doesn't fail. The `check` command
+    // fails and lists the missing images and tiles instead.
+    let missing_image_count = config
+        .terrain_sets
+        .iter()
+        .enumerate()
+        .map(|(set_index, set)| find_missing_images(set_index, set, &images).len())
+        .sum::<usize>();
+    if missing_image_count > 0 {
+        eprintln!(
+            "{missing_image_count} terrain images are missing, so some terrain tiles are left out (run 'check' to list them)"
+        );
+    }
+
+    let mut tiles = Vec::new();
+    for (set_index, set) in config.terrain_sets.iter().enumerate() {
+        let neighbors = terrain_neighbors(config, set.mode);
+
+        if !(set.feather.is_finite() && set.feather >= 0.0) {
+            bail!("expected 'feather' of terrain set {set_index} to not be negative");
+        }
+
+        // Mask images are only used when they are configured, since a
+        // leftover image would otherwise keep the sectors of another mode.
+        let mask_name = set.mask.as_deref();
+        if mask_name.is_none() && directory_path.join("mask.png").exists() {
+            eprintln!(
+                "terrain set {set_index} has a generated mask, and 'mask.png' is only used with 'mask = \"mask.png\"'"
+            );
+        }
+
+        let generated_mask_image;
+        let mask_image = match mask_name {
+            Some(mask_name) => {
+                if !set.mask_angles.is_empty() {
+                    bail!(
+                        "'mask_angles' of terrain set {set_index} can't be used with the mask image {mask_name:?}, which has to be removed to generate the mask"
+                    );
+                }
+
+                match mask_images.entry(mask_name) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let mask_image = load_mask_image(
+                            &directory_path.join(entry.key()),
+                            config,
+                            neighbors.len(),
+                        )?;
+                        entry.insert(mask_image)
+                    }
+                }
+            }
+            None => {
+                generated_mask_image = generate_mask_image(config, set).with_context(|| {
+                    format!("could not generate the mask for terrain set {set_index}")
+                })?;
+                &generated_mask_image
+            }
+        };
+
+        let mask = SectorWeights::new(mask_image, neighbors.len(), set.feather);
+        let combinations = find_combinations(set_index, set, neighbors, &images);
+
+        for combination in combinations {
+            tiles.extend(generate_combinations(
+                &combination,
+                neighbors,
+                &images,
+                &mask,
+                set,
+            ));
+        }
+    }
+
+    Ok(tiles)
+}
+
+/// Finds the terrain images that are missing for some combinations of
+/// neighbors in the terrain sets.
+pub(crate) fn check_terrain_images(config_path: &Path, config: &Config) -> Result<TerrainCoverage> {
+    if config.terrain_sets.is_empty() {
+        return Ok(TerrainCoverage { sets: Vec::new() });
+    }
 
-    let mask_image_path = directory_path.join("mask.png");
-    let mask_image_file = File::open(&mask_image_path)
-        .with_context(|| format!("could not open mask image {mask_image_path:?}"))?;
+    let images = load_images(&config_path.join("terrains"), config)?;
+
+    Ok(terrain_coverage(config, &images))
+}
+
+/// Loads a mask image, and warns about pixels that don't have any of the
+/// colors of the `sector_count` sectors, and about sectors without pixels,
+/// since they are never drawn over.
+fn load_mask_image(path: &Path, config: &Config, sector_count: usize) -> Result<RgbaImage> {
+    let mask_image_file =
+        File::open(path).with_context(|| format!("could not open mask image {path:?}"))?;
     let mask_image_file = BufReader::new(mask_image_file);
     let mask_image = image::load(mask_image_file, image::ImageFormat::Png)
-        .with_context(|| format!("could not load mask image {mask_image_path:?}"))?
+        .with_context(|| format!("could not load mask image {path:?}"))?
         .into_rgba8();
 
-    let images = load_images(&directory_path, config)?;
-    let combinations = find_combinations(config, &images);
+    let [tile_width, tile_height] = config.tile_set.tile_size;
+    if mask_image.dimensions() != (tile_width, tile_height) {
+        bail!(
+            "expected the mask image {path:?} to be {tile_width}x{tile_height}, but found {}x{}",
+            mask_image.width(),
+            mask_image.height()
+        );
+    }
 
-    let mut tiles = Vec::new();
-    for combination in combinations {
-        tiles.extend(generate_combinations(&combination, &images, &mask_image));
+    let sector_colors = &MASK_COLORS[..sector_count];
+    let unmasked_count = mask_image
+        .pixels()
+        .filter(|pixel| !sector_colors.contains(pixel))
+        .count();
+    if unmasked_count > 0 {
+        eprintln!(
+            "{unmasked_count} pixels in the mask image {path:?} don't have any of the {sector_count} sector colors, and will only show the center terrain"
+        );
     }
 
-    Ok(tiles)
+    let missing_sector_count = sector_colors
+        .iter()
+        .filter(|&color| !mask_image.pixels().any(|pixel| pixel == color))
+        .count();
+    if missing_sector_count > 0 {
+        eprintln!(
+            "{missing_sector_count} of the {sector_count} sector colors are missing in the mask image {path:?}, and their sectors will only show the center terrain"
+        );
+    }
+
+    Ok(mask_image)
+}
+
+/// The neighbors that terrains are matched against, in clockwise order. When
+/// both sides and corners are matched, they alternate and start with a side.
+fn terrain_neighbors(config: &Config, mode: TerrainMode) -> &'static [CellNeighbor] {
+    use CellNeighbor::*;
+
+    let shape = config.tile_set.tile_shape;
+    let offset_axis = config.tile_set.tile_offset_axis;
+
+    match (shape, offset_axis, mode) {
+        (TileShape::Square, _, TerrainMode::Sides) => &[TopSide, RightSide, BottomSide, LeftSide],
+        (TileShape::Square, _, TerrainMode::Corners) => &[
+            TopRightCorner,
+            BottomRightCorner,
+            BottomLeftCorner,
+            TopLeftCorner,
+        ],
+        (TileShape::Square, _, TerrainMode::CornersAndSides) => &[
+            TopSide,
+            TopRightCorner,
+            RightSide,
+            BottomRightCorner,
+            BottomSide,
+            BottomLeftCorner,
+            LeftSide,
+            TopLeftCorner,
+        ],
+        (TileShape::Isometric, _, TerrainMode::Sides) => {
+            &[TopRightSide, BottomRightSide, BottomLeftSide, TopLeftSide]
+        }
+        (TileShape::Isometric, _, TerrainMode::Corners) => {
+            &[RightCorner, BottomCorner, LeftCorner, TopCorner]
+        }
+        (TileShape::Isometric, _, TerrainMode::CornersAndSides) => &[
+            TopRightSide,
+            RightCorner,
+            BottomRightSide,
+            BottomCorner,
+            BottomLeftSide,
+            LeftCorner,
+            TopLeftSide,
+            TopCorner,
+        ],
+        (TileShape::Hexagon | TileShape::HalfOffsetSquare, TileOffsetAxis::Horizontal, mode) => {
+            match mode {
+                TerrainMode::Sides => &[
+                    TopLeftSide,
+                    TopRightSide,
+                    RightSide,
+                    BottomRightSide,
+                    BottomLeftSide,
+                    LeftSide,
+                ],
+                TerrainMode::Corners => &[
+                    TopCorner,
+                    TopRightCorner,
+                    BottomRightCorner,
+                    BottomCorner,
+                    BottomLeftCorner,
+                    TopLeftCorner,
+                ],
+                TerrainMode::CornersAndSides => &[
+                    TopLeftSide,
+                    TopCorner,
+                    TopRightSide,
+                    TopRightCorner,
+                    RightSide,
+                    BottomRightCorner,
+                    BottomRightSide,
+                    BottomCorner,
+                    BottomLeftSide,
+                    BottomLeftCorner,
+                    LeftSide,
+                    TopLeftCorner,
+                ],
+            }
+        }
+        (TileShape::Hexagon | TileShape::HalfOffsetSquare, TileOffsetAxis::Vertical, mode) => {
+            match mode {
+                TerrainMode::Sides => &[
+                    TopLeftSide,
+                    TopSide,
+                    TopRightSide,
+                    BottomRightSide,
+                    BottomSide,
+                    BottomLeftSide,
+                ],
+                TerrainMode::Corners => &[
+                    TopLeftCorner,
+                    TopRightCorner,
+                    RightCorner,
+                    BottomRightCorner,
+                    BottomLeftCorner,
+                    LeftCorner,
+                ],
+                TerrainMode::CornersAndSides => &[
+                    TopLeftSide,
+                    TopLeftCorner,
+                    TopSide,
+                    TopRightCorner,
+                    TopRightSide,
+                    RightCorner,
+                    BottomRightSide,
+                    BottomRightCorner,
+                    BottomSide,
+                    BottomLeftCorner,
+                    BottomLeftSide,
+                    LeftCorner,
+                ],
+            }
+        }
+    }
+}
+
+/// Generates a mask image for a terrain set, where each sector spans from the
+/// middle of one neighbor to the middle of the next, unless the set has other
+/// angles for them.
+pub(crate) fn generate_mask_image(config: &Config, set: &TerrainSetConfig) -> Result<RgbaImage> {
+    let [width, height] = config.tile_set.tile_size;
+    let neighbors = terrain_neighbors(config, set.mode);
+    let neighbor_names = || neighbors.iter().map(|neighbor| neighbor.name()).join(", ");
+
+    if let Some(name) = set
+        .mask_angles
+        .keys()
+        .find(|name| !neighbors.iter().any(|neighbor| neighbor.name() == *name))
+    {
+        bail!(
+            "expected {name:?} in 'mask_angles' to be one of {}",
+            neighbor_names()
+        );
+    }
+
+    let angles = neighbors
+        .iter()
+        .map(|&neighbor| match set.mask_angles.get(neighbor.name()) {
+            Some(degrees) => degrees.to_radians(),
+            None => {
+                let [x, y] = neighbor_direction(config, neighbor);
+                (y * height as f64).atan2(x * width as f64)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // The sectors only cover the tile once if the angles go around it once.
+    let turns = angles
+        .iter()
+        .circular_tuple_windows()
+        .map(|(&start, &end)| (end - start).rem_euclid(TAU))
+        .sum::<f64>()
+        / TAU;
+    if (turns - 1.0).abs() > 1e-9 {
+        bail!(
+            "expected the mask angles to be in clockwise order: {}",
+            neighbor_names()
+        );
+    }
+
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let x = x as f64 + 0.5 - width as f64 / 2.0;
+        let y = y as f64 + 0.5 - height as f64 / 2.0;
+        let angle = y.atan2(x);
+
+        let sector = angles
+            .iter()
+            .circular_tuple_windows()
+            .position(|(&start, &end)| {
+                (angle - start).rem_euclid(TAU) < (end - start).rem_euclid(TAU)
+            })
+            .unwrap_or(0);
+
+        MASK_COLORS[sector]
+    }))
+}
+
+/// The direction from the center of a tile towards a neighbor, where `[1, 1]`
+/// is the bottom right corner of the tile's bounding box.
+fn neighbor_direction(config: &Config, neighbor: CellNeighbor) -> [f64; 2] {
+    use CellNeighbor::*;
+
+    let shape = config.tile_set.tile_shape;
+    let offset_axis = config.tile_set.tile_offset_axis;
+
+    let [x, y] = match shape {
+        TileShape::Square => match neighbor {
+            RightSide | RightCorner => [1.0, 0.0],
+            BottomRightSide | BottomRightCorner => [1.0, 1.0],
+            BottomSide | BottomCorner => [0.0, 1.0],
+            BottomLeftSide | BottomLeftCorner => [-1.0, 1.0],
+            LeftSide | LeftCorner => [-1.0, 0.0],
+            TopLeftSide | TopLeftCorner => [-1.0, -1.0],
+            TopSide | TopCorner => [0.0, -1.0],
+            TopRightSide | TopRightCorner => [1.0, -1.0],
+        },
+        TileShape::Isometric => match neighbor {
+            RightSide | RightCorner => [1.0, 0.0],
+            BottomRightSide | BottomRightCorner => [0.5, 0.5],
+            BottomSide | BottomCorner => [0.0, 1.0],
+            BottomLeftSide | BottomLeftCorner => [-0.5, 0.5],
+            LeftSide | LeftCorner => [-1.0, 0.0],
+            TopLeftSide | TopLeftCorner => [-0.5, -0.5],
+            TopSide | TopCorner => [0.0, -1.0],
+            TopRightSide | TopRightCorner => [0.5, -0.5],
+        },
+        TileShape::Hexagon | TileShape::HalfOffsetSquare => {
+            // The shapes are the same for both axes, but with X and Y swapped.
+            let neighbor = match offset_axis {
+                TileOffsetAxis::Horizontal => neighbor,
+                TileOffsetAxis::Vertical => match neighbor {
+                    RightSide => BottomSide,
+                    RightCorner => BottomCorner,
+                    BottomRightSide => BottomRightSide,
+                    BottomRightCorner => BottomRightCorner,
+                    BottomSide => RightSide,
+                    BottomCorner => RightCorner,
+                    BottomLeftSide => TopRightSide,
+                    BottomLeftCorner => TopRightCorner,
+                    LeftSide => TopSide,
+                    LeftCorner => TopCorner,
+                    TopLeftSide => TopLeftSide,
+                    TopLeftCorner => TopLeftCorner,
+                    TopSide => LeftSide,
+                    TopCorner => LeftCorner,
+                    TopRightSide => BottomLeftSide,
+                    TopRightCorner => BottomLeftCorner,
+                },
+            };
+
+            let corner_y = if shape == TileShape::Hexagon {
+                0.5
+            } else {
+                1.0
+            };
+            let side_y = if shape == TileShape::Hexagon {
+                0.75
+            } else {
+                1.0
+            };
+
+            let [x, y] = match neighbor {
+                RightSide | RightCorner => [1.0, 0.0],
+                BottomRightSide => [0.5, side_y],
+                BottomRightCorner => [1.0, corner_y],
+                BottomSide | BottomCorner => [0.0, 1.0],
+                BottomLeftSide => [-0.5, side_y],
+                BottomLeftCorner => [-1.0, corner_y],
+                LeftSide | LeftCorner => [-1.0, 0.0],
+                TopLeftSide => [-0.5, -side_y],
+                TopLeftCorner => [-1.0, -corner_y],
+                TopSide | TopCorner => [0.0, -1.0],
+                TopRightSide => [0.5, -side_y],
+                TopRightCorner => [1.0, -corner_y],
+            };
+
+            match offset_axis {
+                TileOffsetAxis::Horizontal => [x, y],
+                TileOffsetAxis::Vertical => [y, x],
+            }
+        }
+    };
+
+    [x, y]
 }
 
-fn find_combinations(config: &Config, images: &[TerrainImage]) -> Vec<Vec<TerrainId>> {
+/// Mirrors peering bits to match a flipped or transposed alternative tile.
+/// Tiles are transposed before they are flipped, like in Godot. Returns `None`
+/// if the transformed tile doesn't fit the tile shape.
+pub(crate) fn transform_peering_bit(
+    config: &Config,
+    mode: TerrainMode,
+    peering_bit: &PeeringBit,
+    alternative: &AlternativeConfig,
+) -> Option<PeeringBit> {
+    let neighbors = terrain_neighbors(config, mode);
+    let mut transformed = PeeringBit::default();
+
+    for &neighbor in neighbors {
+        let [mut x, mut y] = neighbor_direction(config, neighbor);
+        if alternative.transpose {
+            (x, y) = (y, x);
+        }
+        if alternative.flip_h {
+            x = -x;
+        }
+        if alternative.flip_v {
+            y = -y;
+        }
+
+        let target = neighbors.iter().copied().find(|&target| {
+            let [target_x, target_y] = neighbor_direction(config, target);
+            (target_x - x).abs() < 1e-6 && (target_y - y).abs() < 1e-6
+        })?;
+
+        transformed.set(target, peering_bit.get(neighbor));
+    }
+
+    Some(transformed)
+}
+
+fn find_combinations(
+    set_index: usize,
+    set: &TerrainSetConfig,
+    neighbors: &[CellNeighbor],
+    images: &[TerrainImage],
+) -> Vec<Vec<TerrainId>> {
     let mut possible_combinations = Vec::new();
 
-    for (set_index, set) in config.terrain_sets.iter().enumerate() {
-        // A hexagon can have at most 6 different neighbors, meaning we only need to
-        // consider combination of at most 7 terrains.
-        for length in 1..8 {
-            let combinations = set
-                .terrains
-                .iter()
-                .enumerate()
-                .map(|(terrain_index, _)| TerrainId {
-                    terrain_set: set_index,
-                    terrain: terrain_index,
-                })
-                .combinations(length);
-
-            for combination in combinations {
-                if has_images_for_combination(images, &combination) {
-                    eprintln!("adding combination {combination:?}");
-                    possible_combinations.push(combination);
-                }
+    // A tile can have at most one different terrain per neighbor, meaning we
+    // only need to consider combinations of one more terrain than that.
+    for length in 1..=neighbors.len() + 1 {
+        let combinations = set
+            .terrains
+            .iter()
+            .enumerate()
+            .map(|(terrain_index, _)| TerrainId {
+                terrain_set: set_index,
+                terrain: terrain_index,
+            })
+            .combinations(length);
+
+        for combination in combinations {
+            if has_images_for_combination(images, &combination) {
+                possible_combinations.push(combination);
             }
         }
     }
@@ -74,6 +511,158 @@ fn find_combinations(config: &Config, images: &[TerrainImage]) -> Vec<Vec<Terrai
     possible_combinations
 }
 
+/// Lists the missing images and the tiles they leave out in each terrain set.
+fn terrain_coverage(config: &Config, images: &[TerrainImage]) -> TerrainCoverage {
+    let sets = config
+        .terrain_sets
+        .iter()
+        .enumerate()
+        .map(|(set_index, set)| {
+            let (missing_tiles, tile_count) = find_missing_tiles(config, set_index, set, images);
+
+            TerrainSetCoverage {
+                missing_images: find_missing_images(set_index, set, images),
+                missing_tiles,
+                tile_count,
+            }
+        })
+        .collect();
+
+    TerrainCoverage { sets }
+}
+
+/// Lists the images that a terrain set needs to draw all of its tiles, but
+/// doesn't have. A center terrain needs its own image, an image for each other
+/// terrain on one of its neighbors, and an image for each pair of other
+/// terrains on adjacent neighbors. Each variant of the center terrain needs to
+/// find them, as they would when the tiles are drawn.
+fn find_missing_images(
+    set_index: usize,
+    set: &TerrainSetConfig,
+    images: &[TerrainImage],
+) -> Vec<MissingImage> {
+    let terrains = (0..set.terrains.len())
+        .map(|terrain| TerrainId {
+            terrain_set: set_index,
+            terrain,
+        })
+        .collect::<Vec<_>>();
+
+    let mut missing_images = Vec::new();
+    for &center_terrain in &terrains {
+        let mut variants = images
+            .iter()
+            .filter(|image| image.combination == [center_terrain])
+            .map(|image| image.variant)
+            .collect::<Vec<_>>();
+        if variants.is_empty() {
+            variants.push(0);
+        }
+
+        let others = terrains
+            .iter()
+            .copied()
+            .filter(|&terrain| terrain != center_terrain);
+        let combinations = std::iter::once(vec![center_terrain])
+            .chain(others.clone().map(|other| vec![center_terrain, other]))
+            .chain(
+                others
+                    .tuple_combinations()
+                    .map(|(other1, other2)| vec![center_terrain, other1, other2]),
+            );
+
+        for combination in combinations {
+            let is_found = variants.iter().all(|&variant| {
+                find_image_for_combination(images, &mut combination.clone(), variant).is_some()
+            });
+
+            if !is_found {
+                missing_images.push(MissingImage {
+                    terrains: combination
+                        .iter()
+                        .map(|terrain| set.terrains[terrain.terrain].name.clone())
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    missing_images
+}
+
+/// Lists the tiles of a terrain set that can't be generated, the same way as
+/// `find_combinations` and `generate_combinations` find them, together with
+/// the number of tiles the set would have with all images.
+fn find_missing_tiles(
+    config: &Config,
+    set_index: usize,
+    set: &TerrainSetConfig,
+    images: &[TerrainImage],
+) -> (Vec<MissingTile>, usize) {
+    let neighbors = terrain_neighbors(config, set.mode);
+    let terrains = (0..set.terrains.len()).map(|terrain| TerrainId {
+        terrain_set: set_index,
+        terrain,
+    });
+
+    let mut missing_tiles = Vec::new();
+    let mut tile_count = 0;
+
+    for length in 1..=neighbors.len() + 1 {
+        for combination in terrains.clone().combinations(length) {
+            let has_images = has_images_for_combination(images, &combination);
+
+            for &center_terrain in &combination {
+                let variants = images
+                    .iter()
+                    .filter(|image| image.combination == [center_terrain])
+                    .map(|image| image.variant)
+                    .collect::<Vec<_>>();
+
+                let others = combination
+                    .iter()
+                    .copied()
+                    .filter(|&terrain| terrain != center_terrain)
+                    .collect::<Vec<_>>();
+
+                for sides in drawable_sides(center_terrain, neighbors, &others) {
+                    tile_count += 1;
+
+                    let is_drawable = has_images
+                        && variants.iter().any(|&variant| {
+                            has_sector_images(center_terrain, &sides, images, variant)
+                        });
+
+                    if !is_drawable {
+                        let terrain_name =
+                            |terrain: TerrainId| set.terrains[terrain.terrain].name.clone();
+
+                        missing_tiles.push(MissingTile {
+                            terrain: terrain_name(center_terrain),
+                            neighbors: neighbors
+                                .iter()
+                                .zip(&sides)
+                                .filter_map(|(neighbor, side)| {
+                                    side.map(|terrain| (neighbor.name(), terrain_name(terrain)))
+                                })
+                                .collect(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    (missing_tiles, tile_count)
+}
+
+/// The number of animation frames in each tile of a terrain set.
+fn frame_count(set: &TerrainSetConfig) -> u32 {
+    set.animation
+        .as_ref()
+        .map_or(1, |animation| animation.frames.len() as u32)
+}
+
 fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImage>> {
     let mut terrain_images = Vec::new();
 
@@ -84,7 +673,7 @@ fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImag
         [tile_width, tile_height * 2],
     ];
 
-    for entry in std::fs::read_dir(&directory_path)
+    for entry in std::fs::read_dir(directory_path)
         .with_context(|| format!("could not open {directory_path:?}"))?
     {
         let entry =
@@ -110,10 +699,29 @@ fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImag
 
         let stem = str::from_utf8(stem.as_bytes()).expect("file name should be valid UTF-8");
 
-        if stem == "mask" {
+        if stem == "mask"
+            || Some(stem) == MASK_TEMPLATE_NAME.strip_suffix(".png")
+            || config.terrain_sets.iter().any(|set| {
+                set.mask
+                    .as_deref()
+                    .and_then(|mask| mask.strip_suffix(".png"))
+                    == Some(stem)
+            })
+        {
             continue;
         }
 
+        // Variants are numbered, as in 'TerrainName.1.png'.
+        let (stem, variant) = match stem.rsplit_once('.') {
+            Some((stem, variant)) => {
+                let variant = variant.parse::<u32>().with_context(|| {
+                    format!("expected the variant number of {path:?} to be an integer")
+                })?;
+                (stem, variant)
+            }
+            None => (stem, 0),
+        };
+
         let mut parts = stem.split('-');
         let terrain_names = parts.by_ref().take(3).map(str::trim).collect::<Vec<_>>();
 
@@ -159,7 +767,12 @@ fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImag
             }
         }
 
-        let expected_size = expected_sizes[terrains.len() - 1];
+        // Animation frames are side by side.
+        let [width, height] = expected_sizes[terrains.len() - 1];
+        let expected_size = [
+            width * frame_count(&config.terrain_sets[center_terrain.terrain_set]),
+            height,
+        ];
         if [image.width(), image.height()] != expected_size {
             bail!(
                 "expected an image of size {}x{}, but found  {}x{} in {path:?}",
@@ -172,6 +785,7 @@ fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImag
 
         terrain_images.push(TerrainImage {
             combination: terrains,
+            variant,
             image,
         })
     }
@@ -179,90 +793,402 @@ fn load_images(directory_path: &Path, config: &Config) -> Result<Vec<TerrainImag
     Ok(terrain_images)
 }
 
+/// Generates the tiles for a combination of terrains. Each terrain with its
+/// own image is used as the center terrain, surrounded by all of the other
+/// terrains in the combination, so that each tile is only generated once.
+/// There's one tile per variant of the center terrain's image.
 fn generate_combinations(
     terrains: &[TerrainId],
+    neighbors: &[CellNeighbor],
     images: &[TerrainImage],
-    mask_image: &RgbaImage,
+    mask: &SectorWeights,
+    set: &TerrainSetConfig,
 ) -> Vec<TerrainTile> {
     let mut tiles = Vec::new();
-    let center_terrain = terrains[0];
-    let main_image = images
-        .iter()
-        .find(|image| image.combination == [center_terrain])
-        .expect("an image for only the center terrain should exist");
-    let none_image = main_image
-        .image
-        .view(0, 0, main_image.image.width(), mask_image.height());
-
-    for sides in itertools::repeat_n(
-        std::iter::once(None).chain(terrains.iter().copied().map(Some)),
-        6,
-    )
-    .multi_cartesian_product()
-    {
-        let mut image = RgbaImage::new(mask_image.width(), mask_image.height());
-        image
-            .copy_from(&*none_image, 0, 0)
-            .expect("combination image should fit a tile");
 
-        for ((index, side), (_, next)) in sides.iter().copied().enumerate().circular_tuple_windows()
+    for &center_terrain in terrains {
+        let mut main_images = images
+            .iter()
+            .filter(|image| image.combination == [center_terrain])
+            .collect::<Vec<_>>();
+        main_images.sort_by_key(|image| image.variant);
+
+        let variant_weights = &set.terrains[center_terrain.terrain].variant_weights;
+
+        let others = terrains
+            .iter()
+            .copied()
+            .filter(|&terrain| terrain != center_terrain)
+            .collect::<Vec<_>>();
+
+        for sides in drawable_sides(center_terrain, neighbors, &others) {
+            for main_image in &main_images {
+                let frames = (0..frame_count(set))
+                    .map(|frame| draw_tile(center_terrain, &sides, main_image, images, mask, frame))
+                    .collect::<Option<Vec<_>>>();
+
+                let Some(frames) = frames else {
+                    continue;
+                };
+
+                tiles.push(TerrainTile {
+                    terrain: center_terrain,
+                    terrains_peering_bit: sides_to_peering_bit(neighbors, &sides),
+                    probability: variant_weights
+                        .get(main_image.variant as usize)
+                        .copied()
+                        .unwrap_or(1.0),
+                    frames,
+                });
+            }
+        }
+    }
+
+    tiles
+}
+
+/// Draws an animation frame of a tile by picking each mask sector from the
+/// image for the center terrain and its two surrounding neighbors, and blending
+/// them where the sectors overlap. Returns `None` if any of the needed images
+/// are missing.
+fn draw_tile(
+    center_terrain: TerrainId,
+    sides: &[Option<TerrainId>],
+    main_image: &TerrainImage,
+    images: &[TerrainImage],
+    mask: &SectorWeights,
+    frame: u32,
+) -> Option<RgbaImage> {
+    let frame_x = frame * mask.width;
+    let none_image = main_image.image.view(frame_x, 0, mask.width, mask.height);
+
+    let mut image = RgbaImage::new(mask.width, mask.height);
+    image
+        .copy_from(&*none_image, 0, 0)
+        .expect("combination image should fit a tile");
+
+    // The weight of each pixel that has been drawn so far, starting with the
+    // part that isn't covered by any sector.
+    let mut coverage = mask.uncovered.clone();
+
+    for ((index, side), (_, next)) in sides.iter().copied().enumerate().circular_tuple_windows() {
+        let weights = &mask.sectors[index];
+        for (covered, weight) in coverage.iter_mut().zip(weights) {
+            *covered += weight;
+        }
+
+        let mut combination = get_terrain_combination(center_terrain, side, next);
+        let (combo_image, swapped) =
+            find_image_for_combination(images, &mut combination, main_image.variant)?;
+        let parity = index as u32 % 2;
+
+        // Sectors without neighbors show the center terrain image, which still
+        // has to be blended with the sectors before them.
+        let sub_image_index = match *combination {
+            [_] => match (side.is_some(), next.is_some()) {
+                (true, true) => Some(3),
+                (true, false) => Some(1 + parity),
+                (false, true) => Some(2 - parity),
+                (false, false) => None,
+            },
+            [_, other] => match (side == Some(other), next == Some(other)) {
+                (true, true) => Some(3),
+                (true, false) => Some(1 + parity),
+                (false, true) => Some(2 - parity),
+                (false, false) => unreachable!("one of the sides should be the other terrain"),
+            },
+            // The first sub-image has the first terrain on the even
+            // neighbors, and the second has it on the odd neighbors.
+            [_, _, _] if swapped => Some(1 - parity),
+            [_, _, _] => Some(parity),
+            _ => unreachable!("combinations have at most three terrains"),
+        };
+
+        let source = match sub_image_index {
+            Some(sub_image_index) => combo_image.image.view(
+                frame_x,
+                mask.height * sub_image_index,
+                mask.width,
+                mask.height,
+            ),
+            None => none_image,
+        };
+
+        for (((dst, (_, _, src)), &weight), &covered) in image
+            .pixels_mut()
+            .zip(source.pixels())
+            .zip(weights)
+            .zip(&coverage)
         {
+            if weight <= 0.0 {
+                continue;
+            }
+
+            if weight >= covered {
+                *dst = src
+            } else {
+                *dst = blend_linear(*dst, src, weight / covered);
+            }
+        }
+    }
+
+    Some(image)
+}
+
+/// Checks if there's an image for every mask sector of a tile, as `draw_tile`
+/// needs.
+fn has_sector_images(
+    center_terrain: TerrainId,
+    sides: &[Option<TerrainId>],
+    images: &[TerrainImage],
+    variant: u32,
+) -> bool {
+    sides
+        .iter()
+        .copied()
+        .circular_tuple_windows()
+        .all(|(side, next)| {
             let mut combination = get_terrain_combination(center_terrain, side, next);
-            let (combo_image, swapped) = find_image_for_combination(images, &mut combination)
-                .expect("combination should have an image");
-
-            let sub_image_index = match &*combination {
-                &[_] => match (side.is_some(), next.is_some()) {
-                    (true, true) => 3,
-                    (true, false) => 1 + index as u32 % 2,
-                    (false, true) => 2 - index as u32 % 2,
-                    (false, false) => continue,
-                },
-                _ => unimplemented!(),
-            };
+            find_image_for_combination(images, &mut combination, variant).is_some()
+        })
+}
 
-            let source = combo_image.image.view(
-                0,
-                mask_image.height() * sub_image_index,
-                combo_image.image.width(),
-                mask_image.height(),
-            );
+/// How much each mask sector covers each pixel of a tile, from 0 to 1.
+struct SectorWeights {
+    width: u32,
+    height: u32,
+    /// The weights of each sector, by pixel index.
+    sectors: Vec<Vec<f32>>,
+    /// The part of each pixel that isn't covered by any sector.
+    uncovered: Vec<f32>,
+}
+
+impl SectorWeights {
+    /// Makes the weights from the sector colors of a mask image, where each
+    /// pixel is covered by the sectors within `feather` pixels from it.
+    fn new(mask_image: &RgbaImage, sector_count: usize, feather: f64) -> Self {
+        let (width, height) = mask_image.dimensions();
+        let sector_colors = &MASK_COLORS[..sector_count];
+        let pixel_sectors = mask_image
+            .pixels()
+            .map(|pixel| sector_colors.iter().position(|color| color == pixel))
+            .collect::<Vec<_>>();
 
-            let mask_color = MASK_COLORS[index];
-            for ((dst, (_, _, src)), mask) in image
-                .pixels_mut()
-                .zip(source.pixels())
-                .zip(mask_image.pixels())
-            {
-                if *mask == mask_color {
-                    *dst = src
+        let radius = feather.floor() as i64;
+        let offsets = (-radius..=radius)
+            .cartesian_product(-radius..=radius)
+            .filter(|&(x, y)| ((x * x + y * y) as f64) <= feather * feather)
+            .collect::<Vec<_>>();
+
+        let pixel_count = pixel_sectors.len();
+        let mut sample_counts = vec![vec![0u32; pixel_count]; sector_count];
+        for (y, x) in (0..height as i64).cartesian_product(0..width as i64) {
+            let index = (y * width as i64 + x) as usize;
+            for &(offset_x, offset_y) in &offsets {
+                // The edge pixels are repeated outside of the tile.
+                let sample_x = (x + offset_x).clamp(0, width as i64 - 1);
+                let sample_y = (y + offset_y).clamp(0, height as i64 - 1);
+                let sample_index = (sample_y * width as i64 + sample_x) as usize;
+
+                if let Some(sector) = pixel_sectors[sample_index] {
+                    sample_counts[sector][index] += 1;
                 }
             }
         }
 
-        tiles.push(TerrainTile {
-            terrain: center_terrain,
-            terrains_peering_bit: sides_to_peering_bit(&sides),
-            image,
-        });
+        let offset_count = offsets.len() as u32;
+        let uncovered = (0..pixel_count)
+            .map(|index| {
+                let covered = sample_counts
+                    .iter()
+                    .map(|counts| counts[index])
+                    .sum::<u32>();
+                (offset_count - covered) as f32 / offset_count as f32
+            })
+            .collect();
+        let sectors = sample_counts
+            .into_iter()
+            .map(|counts| {
+                counts
+                    .into_iter()
+                    .map(|count| count as f32 / offset_count as f32)
+                    .collect()
+            })
+            .collect();
+
+        SectorWeights {
+            width,
+            height,
+            sectors,
+            uncovered,
+        }
     }
+}
 
-    tiles
+/// Mixes two colors in linear color space, with premultiplied alpha, where
+/// `amount` is how much of `source` is in the result.
+fn blend_linear(destination: Rgba<u8>, source: Rgba<u8>, amount: f32) -> Rgba<u8> {
+    let destination_alpha = destination[3] as f32 / 255.0;
+    let source_alpha = source[3] as f32 / 255.0;
+    let alpha = destination_alpha + (source_alpha - destination_alpha) * amount;
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let [red, green, blue] = [0, 1, 2].map(|channel| {
+        let destination = srgb_to_linear(destination[channel]) * destination_alpha;
+        let source = srgb_to_linear(source[channel]) * source_alpha;
+        linear_to_srgb((destination + (source - destination) * amount) / alpha)
+    });
+
+    Rgba([red, green, blue, (alpha * 255.0).round() as u8])
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (value * 255.0).round() as u8
+}
+
+/// Lists the neighbor terrains of every drawable tile with the center terrain,
+/// where each of the other terrains is on at least one neighbor. The terrains
+/// are chosen one neighbor at a time, in the order of a cartesian product, and
+/// each corner is checked as soon as both of its sides are chosen.
+fn drawable_sides(
+    center_terrain: TerrainId,
+    neighbors: &[CellNeighbor],
+    others: &[TerrainId],
+) -> Vec<Vec<Option<TerrainId>>> {
+    let choices = [None, Some(center_terrain)]
+        .into_iter()
+        .chain(others.iter().copied().map(Some))
+        .collect::<Vec<_>>();
+
+    let mut all_sides = Vec::new();
+    add_drawable_sides(
+        center_terrain,
+        neighbors,
+        others,
+        &choices,
+        &mut Vec::with_capacity(neighbors.len()),
+        &mut all_sides,
+    );
+
+    all_sides
+}
+
+fn add_drawable_sides(
+    center_terrain: TerrainId,
+    neighbors: &[CellNeighbor],
+    others: &[TerrainId],
+    choices: &[Option<TerrainId>],
+    sides: &mut Vec<Option<TerrainId>>,
+    all_sides: &mut Vec<Vec<Option<TerrainId>>>,
+) {
+    let count = neighbors.len();
+    let index = sides.len();
+
+    if index == count {
+        // The first and last neighbors are next to each other, so they are
+        // checked when all neighbors have terrains.
+        if is_drawable_neighbor(center_terrain, neighbors, sides, 0)
+            && is_drawable_neighbor(center_terrain, neighbors, sides, count - 1)
+        {
+            all_sides.push(sides.clone());
+        }
+
+        return;
+    }
+
+    for &choice in choices {
+        sides.push(choice);
+
+        let missing_others = others
+            .iter()
+            .filter(|&&other| !sides.contains(&Some(other)))
+            .count();
+        let is_possible = missing_others <= count - sides.len()
+            && (index < 2 || is_drawable_neighbor(center_terrain, neighbors, sides, index - 1));
+
+        if is_possible {
+            add_drawable_sides(center_terrain, neighbors, others, choices, sides, all_sides);
+        }
+
+        sides.pop();
+    }
+}
+
+/// Checks if a corner of a tile is consistent with its sides. A corner may
+/// only have the center terrain if both of its sides have it, and may otherwise
+/// only have the terrain of one of its sides, unless both sides have the center
+/// terrain. This reduces a two terrain square set to the 47 tile "blob" set.
+/// The neighbors before and after `index` need to have terrains in `sides`.
+fn is_drawable_neighbor(
+    center_terrain: TerrainId,
+    neighbors: &[CellNeighbor],
+    sides: &[Option<TerrainId>],
+    index: usize,
+) -> bool {
+    let count = neighbors.len();
+    let previous = (index + count - 1) % count;
+    let next = (index + 1) % count;
+
+    if !neighbors[index].is_corner()
+        || neighbors[previous].is_corner()
+        || neighbors[next].is_corner()
+    {
+        return true;
+    }
+
+    let corner = sides[index];
+    let sides_are_center =
+        sides[previous] == Some(center_terrain) && sides[next] == Some(center_terrain);
+
+    if corner == Some(center_terrain) {
+        sides_are_center
+    } else {
+        sides_are_center || sides[previous] == corner || sides[next] == corner
+    }
 }
 
+/// Finds the image for a combination, preferring the given variant over the
+/// image without a variant number.
 fn find_image_for_combination<'a>(
     images: &'a [TerrainImage],
     combination: &mut [TerrainId],
+    variant: u32,
 ) -> Option<(&'a TerrainImage, bool)> {
-    let found_image = images.iter().find(|image| image.combination == combination);
+    let find_image = |combination: &[TerrainId]| {
+        let mut matching = images
+            .iter()
+            .filter(move |image| image.combination == combination);
+
+        matching
+            .clone()
+            .find(|image| image.variant == variant)
+            .or_else(|| matching.find(|image| image.variant == 0))
+    };
+
+    let found_image = find_image(combination);
 
     if found_image.is_none() && combination.len() == 3 {
-        combination.swap(2, 2);
+        combination.swap(1, 2);
 
-        images
-            .iter()
-            .find(|image| image.combination == combination)
-            .map(|image| (image, true))
+        find_image(combination).map(|image| (image, true))
     } else {
         found_image.map(|image| (image, false))
     }
@@ -282,6 +1208,12 @@ fn get_terrain_combination(
             vec![center_terrain]
         }
         (None, Some(other)) | (Some(other), None) => vec![center_terrain, other],
+        (Some(other1), Some(other2)) if other1 == other2 || other1 == center_terrain => {
+            vec![center_terrain, other2]
+        }
+        (Some(other1), Some(other2)) if other2 == center_terrain => {
+            vec![center_terrain, other1]
+        }
         (Some(other1), Some(other2)) => vec![center_terrain, other1, other2],
     }
 }
@@ -356,17 +1288,15 @@ fn has_images_for_combination(images: &[TerrainImage], combination: &[TerrainId]
     matches_one_to_any && matches_one_to_one && matches_one_to_two
 }
 
-fn sides_to_peering_bit(sides: &[Option<TerrainId>]) -> PeeringBit {
-    assert_eq!(sides.len(), 6);
+fn sides_to_peering_bit(neighbors: &[CellNeighbor], sides: &[Option<TerrainId>]) -> PeeringBit {
+    assert_eq!(sides.len(), neighbors.len());
 
-    PeeringBit {
-        top_left_side: sides[0].map(|t| t.terrain as u32),
-        top_side: sides[1].map(|t| t.terrain as u32),
-        top_right_side: sides[2].map(|t| t.terrain as u32),
-        bottom_right_side: sides[3].map(|t| t.terrain as u32),
-        bottom_side: sides[4].map(|t| t.terrain as u32),
-        bottom_left_side: sides[5].map(|t| t.terrain as u32),
+    let mut peering_bit = PeeringBit::default();
+    for (&neighbor, side) in neighbors.iter().zip(sides) {
+        peering_bit.set(neighbor, side.map(|t| t.terrain as u32));
     }
+
+    peering_bit
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -378,10 +1308,328 @@ pub(crate) struct TerrainId {
 pub(crate) struct TerrainTile {
     pub terrain: TerrainId,
     pub terrains_peering_bit: PeeringBit,
-    pub image: RgbaImage,
+    /// The weight of the tile's variant.
+    pub probability: f64,
+    /// The animation frames, or only the tile image if it's not animated.
+    pub frames: Vec<RgbaImage>,
 }
 
 struct TerrainImage {
     combination: Vec<TerrainId>,
+    variant: u32,
     image: RgbaImage,
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use itertools::Itertools;
+
+    use super::{
+        blend_linear, draw_tile, drawable_sides, find_image_for_combination, is_drawable_neighbor,
+        terrain_neighbors, SectorWeights, TerrainId, TerrainImage, MASK_COLORS,
+    };
+    use crate::config::{Config, TerrainMode};
+
+    fn config(tile_shape: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+[tile_set]
+tile_size = [16, 16]
+tile_shape = "{tile_shape}"
+
+[godot]
+project_path = "."
+tile_set_path = "res://tile_set.tres"
+"#
+        ))
+        .expect("the config should parse")
+    }
+
+    fn terrain(terrain: usize) -> TerrainId {
+        TerrainId {
+            terrain_set: 0,
+            terrain,
+        }
+    }
+
+    /// A 2x2 tile image with four sub-images, where the red channel is `id` and
+    /// the green channel is the index of the sub-image.
+    fn terrain_image(combination: &[TerrainId], id: u8) -> TerrainImage {
+        TerrainImage {
+            combination: combination.to_vec(),
+            variant: 0,
+            image: RgbaImage::from_fn(2, 8, |_, y| Rgba([id, (y / 2) as u8, 0, 255])),
+        }
+    }
+
+    /// Draws a 2x2 square tile, with one pixel per sector, and returns the
+    /// image ID and sub-image index of each sector. The sides are top, right,
+    /// bottom and left, as for square tiles in "match_sides" mode.
+    fn drawn_sectors(sides: [Option<TerrainId>; 4], images: &[TerrainImage]) -> [(u8, u8); 4] {
+        let sector_pixels = [(1, 0), (1, 1), (0, 1), (0, 0)];
+        let mask_image = RgbaImage::from_fn(2, 2, |x, y| {
+            let sector = sector_pixels
+                .iter()
+                .position(|&pixel| pixel == (x, y))
+                .expect("every pixel should have a sector");
+            MASK_COLORS[sector]
+        });
+        let mask = SectorWeights::new(&mask_image, 4, 0.0);
+
+        let tile = draw_tile(terrain(0), &sides, &images[0], images, &mask, 0)
+            .expect("the tile should have all images");
+
+        sector_pixels.map(|(x, y)| {
+            let pixel = tile.get_pixel(x, y);
+            (pixel[0], pixel[1])
+        })
+    }
+
+    /// Lists all combinations of neighbor terrains, and keeps the ones where
+    /// every neighbor is drawable, without any pruning.
+    fn all_drawable_sides(
+        center_terrain: TerrainId,
+        mode: TerrainMode,
+        config: &Config,
+        others: &[TerrainId],
+    ) -> Vec<Vec<Option<TerrainId>>> {
+        let neighbors = terrain_neighbors(config, mode);
+        let choices = [None, Some(center_terrain)]
+            .into_iter()
+            .chain(others.iter().copied().map(Some));
+
+        itertools::repeat_n(choices, neighbors.len())
+            .multi_cartesian_product()
+            .filter(|sides| others.iter().all(|&other| sides.contains(&Some(other))))
+            .filter(|sides| {
+                (0..neighbors.len())
+                    .all(|index| is_drawable_neighbor(center_terrain, neighbors, sides, index))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_terrains_make_the_blob_set() {
+        let config = config("square");
+        let neighbors = terrain_neighbors(&config, TerrainMode::CornersAndSides);
+        let [grass, water] = [terrain(0), terrain(1)];
+
+        for (center, other) in [(grass, water), (water, grass)] {
+            let is_filled = |sides: &Vec<Option<TerrainId>>| sides.iter().all(Option::is_some);
+            let with_other = drawable_sides(center, neighbors, &[other])
+                .into_iter()
+                .filter(is_filled)
+                .count();
+            let without_other = drawable_sides(center, neighbors, &[])
+                .into_iter()
+                .filter(is_filled)
+                .count();
+
+            assert_eq!(with_other + without_other, 47);
+        }
+
+        // Neighbors without terrains are a second terrain of their own.
+        assert_eq!(drawable_sides(grass, neighbors, &[]).len(), 47);
+    }
+
+    #[test]
+    fn pruning_keeps_every_drawable_combination() {
+        let others = [terrain(1), terrain(2)];
+
+        for (tile_shape, mode) in [
+            ("square", TerrainMode::CornersAndSides),
+            ("square", TerrainMode::Corners),
+            ("isometric", TerrainMode::CornersAndSides),
+            ("hexagon", TerrainMode::Sides),
+        ] {
+            let config = config(tile_shape);
+            let neighbors = terrain_neighbors(&config, mode);
+
+            for other_count in 0..=others.len() {
+                let others = &others[..other_count];
+
+                assert_eq!(
+                    drawable_sides(terrain(0), neighbors, others),
+                    all_drawable_sides(terrain(0), mode, &config, others),
+                    "{tile_shape} tiles in {mode:?} mode with {other_count} other terrains",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corners_follow_their_sides() {
+        let config = config("square");
+        let neighbors = terrain_neighbors(&config, TerrainMode::CornersAndSides);
+        let [grass, water, sand] = [Some(terrain(0)), Some(terrain(1)), Some(terrain(2))];
+
+        // The top right corner, between the top and right sides.
+        let is_drawable = |top, corner, right| {
+            let mut sides = vec![grass; neighbors.len()];
+            sides[0] = top;
+            sides[1] = corner;
+            sides[2] = right;
+            is_drawable_neighbor(terrain(0), neighbors, &sides, 1)
+        };
+
+        assert!(is_drawable(grass, grass, grass));
+        assert!(is_drawable(grass, water, grass));
+        assert!(is_drawable(water, water, grass));
+        assert!(is_drawable(water, None, None));
+        assert!(is_drawable(water, sand, sand));
+
+        // Godot can't paint a corner that matches the center or neither of its
+        // sides, unless both sides match the center.
+        assert!(!is_drawable(water, grass, grass));
+        assert!(!is_drawable(water, sand, grass));
+        assert!(!is_drawable(None, water, grass));
+    }
+
+    #[test]
+    fn mirrored_junction_images_are_found() {
+        let [grass, water, sand] = [terrain(0), terrain(1), terrain(2)];
+
+        let images = [terrain_image(&[grass, sand, water], 1)];
+        let mut combination = vec![grass, water, sand];
+        let (image, swapped) = find_image_for_combination(&images, &mut combination, 0)
+            .expect("the mirrored image should be found");
+        assert_eq!(image.combination, [grass, sand, water]);
+        assert_eq!(combination, [grass, sand, water]);
+        assert!(swapped);
+
+        // The image in the same order is preferred.
+        let images = [
+            terrain_image(&[grass, sand, water], 1),
+            terrain_image(&[grass, water, sand], 2),
+        ];
+        let mut combination = vec![grass, water, sand];
+        let (image, swapped) = find_image_for_combination(&images, &mut combination, 0)
+            .expect("the image should be found");
+        assert_eq!(image.combination, [grass, water, sand]);
+        assert!(!swapped);
+
+        // Only junctions are mirrored.
+        let images = [terrain_image(&[water, grass], 1)];
+        assert!(find_image_for_combination(&images, &mut [grass, water], 0).is_none());
+    }
+
+    #[test]
+    fn junction_sectors_use_the_matching_sub_images() {
+        let [grass, water, sand] = [terrain(0), terrain(1), terrain(2)];
+        let images = [
+            terrain_image(&[grass], 0),
+            terrain_image(&[grass, water], 1),
+            terrain_image(&[grass, sand], 2),
+            terrain_image(&[grass, water, sand], 3),
+        ];
+        let mirrored_images = [
+            terrain_image(&[grass], 0),
+            terrain_image(&[grass, water], 1),
+            terrain_image(&[grass, sand], 2),
+            terrain_image(&[grass, sand, water], 4),
+        ];
+
+        // Water on the top side, which is even, and sand on the right side,
+        // which is odd. The first sub-image of Grass-Water-Sand.png has water
+        // on the even sides, and the second one of Grass-Sand-Water.png has
+        // sand on the odd sides.
+        let sides = [Some(water), Some(sand), Some(grass), Some(grass)];
+        assert_eq!(
+            drawn_sectors(sides, &images),
+            [(3, 0), (2, 2), (0, 3), (1, 1)]
+        );
+        assert_eq!(
+            drawn_sectors(sides, &mirrored_images),
+            [(4, 1), (2, 2), (0, 3), (1, 1)]
+        );
+
+        // Water on the right side, which is odd, and sand on the bottom side,
+        // which is even.
+        let sides = [Some(grass), Some(water), Some(sand), Some(grass)];
+        assert_eq!(
+            drawn_sectors(sides, &images),
+            [(1, 2), (3, 1), (2, 1), (0, 3)]
+        );
+        assert_eq!(
+            drawn_sectors(sides, &mirrored_images),
+            [(1, 2), (4, 0), (2, 1), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn sector_weights_add_up_to_one() {
+        // Four 4x4 quadrants, with an unmasked pixel in the middle of each.
+        let mask_image = RgbaImage::from_fn(8, 8, |x, y| {
+            if (x % 4, y % 4) == (2, 2) {
+                Rgba([0, 0, 0, 0])
+            } else {
+                MASK_COLORS[(x / 4 + y / 4 * 2) as usize]
+            }
+        });
+
+        for feather in [0.0, 1.0, 1.5, 3.0] {
+            let weights = SectorWeights::new(&mask_image, 4, feather);
+            assert_eq!(weights.sectors.len(), 4);
+
+            for index in 0..64 {
+                let total = weights.uncovered[index]
+                    + weights
+                        .sectors
+                        .iter()
+                        .map(|sector| sector[index])
+                        .sum::<f32>();
+                assert!(
+                    (total - 1.0).abs() < 1e-5,
+                    "pixel {index} adds up to {total} with feather {feather}"
+                );
+            }
+        }
+
+        // Without feathering, each pixel is only covered by its own sector.
+        let weights = SectorWeights::new(&mask_image, 4, 0.0);
+        assert_eq!(weights.sectors[0][0], 1.0);
+        assert_eq!(weights.sectors[1][0], 0.0);
+        assert_eq!(weights.uncovered[2 * 8 + 2], 1.0);
+
+        // With feathering, pixels at the sector edges are shared.
+        let weights = SectorWeights::new(&mask_image, 4, 1.0);
+        assert!(weights.sectors[0][3] > 0.0 && weights.sectors[1][3] > 0.0);
+    }
+
+    #[test]
+    fn blending_the_same_color_keeps_it() {
+        let colors = [
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+            Rgba([12, 128, 250, 255]),
+            Rgba([200, 100, 50, 128]),
+            Rgba([1, 2, 3, 1]),
+        ];
+
+        for color in colors {
+            for amount in [0.0, 0.25, 0.5, 1.0] {
+                assert_eq!(blend_linear(color, color, amount), color);
+            }
+        }
+    }
+
+    #[test]
+    fn blending_follows_the_amount() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+
+        assert_eq!(blend_linear(red, blue, 0.0), red);
+        assert_eq!(blend_linear(red, blue, 1.0), blue);
+        // Half of each, in linear color space.
+        assert_eq!(blend_linear(red, blue, 0.5), Rgba([188, 0, 188, 255]));
+
+        // Transparent pixels don't add their color.
+        let transparent = Rgba([0, 255, 0, 0]);
+        assert_eq!(blend_linear(transparent, red, 0.5), Rgba([255, 0, 0, 128]));
+        assert_eq!(
+            blend_linear(transparent, transparent, 0.5),
+            Rgba([0, 0, 0, 0])
+        );
+    }
+}
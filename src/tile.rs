@@ -1,13 +1,14 @@
 use std::{fs::File, io::BufReader, path::Path};
 
 use anyhow::{bail, Context, Result};
-use image::RgbaImage;
+use image::{GenericImageView, RgbaImage};
 
 use crate::config::{Config, TileConfig};
 
 pub(crate) struct Tile<'a> {
     pub config: &'a TileConfig,
-    pub image: RgbaImage,
+    /// The animation frames, or only the tile image if it's not animated.
+    pub frames: Vec<RgbaImage>,
 }
 
 pub(crate) fn load_tiles<'a>(config_path: &Path, config: &'a Config) -> Result<Vec<Tile<'a>>> {
@@ -16,6 +17,13 @@ pub(crate) fn load_tiles<'a>(config_path: &Path, config: &'a Config) -> Result<V
     let mut tiles = vec![];
 
     for tile in &config.tiles {
+        // Each frame of a multi-cell tile covers its whole block of cells,
+        // including the separation between them.
+        let [tile_width, tile_height] = [0, 1].map(|axis| {
+            let separation = config.tile_set.separation[axis];
+            tile.size[axis] * (config.tile_set.tile_size[axis] + separation) - separation
+        });
+
         let path = directory_path.join(format!("{}.png", tile.name));
         let image_file = File::open(&path).with_context(|| format!("could not open {path:?}"))?;
         let image_file = BufReader::new(image_file);
@@ -23,19 +31,54 @@ pub(crate) fn load_tiles<'a>(config_path: &Path, config: &'a Config) -> Result<V
             .with_context(|| format!("could not load {path:?}"))?
             .into_rgba8();
 
-        if [image.width(), image.height()] != config.tile_set.tile_size {
+        let frame_count = tile
+            .animation
+            .as_ref()
+            .map_or(1, |animation| animation.frames.len() as u32);
+
+        // Animation frames are either side by side or stacked.
+        let frame_offset = if [image.width(), image.height()]
+            == [tile_width * frame_count, tile_height]
+        {
+            [tile_width, 0]
+        } else if [image.width(), image.height()] == [tile_width, tile_height * frame_count] {
+            [0, tile_height]
+        } else if frame_count > 1 {
+            bail!(
+                "expected an image of size {}x{} or {}x{}, with {frame_count} frames, but found {}x{} in {path:?}",
+                tile_width * frame_count,
+                tile_height,
+                tile_width,
+                tile_height * frame_count,
+                image.width(),
+                image.height()
+            );
+        } else {
             bail!(
                 "expected an image of size {}x{}, but found  {}x{} in {path:?}",
-                config.tile_set.tile_size[0],
-                config.tile_set.tile_size[1],
+                tile_width,
+                tile_height,
                 image.width(),
                 image.height()
             );
-        }
+        };
+
+        let frames = (0..frame_count)
+            .map(|frame| {
+                image
+                    .view(
+                        frame * frame_offset[0],
+                        frame * frame_offset[1],
+                        tile_width,
+                        tile_height,
+                    )
+                    .to_image()
+            })
+            .collect();
 
         tiles.push(Tile {
             config: tile,
-            image,
+            frames,
         })
     }
 